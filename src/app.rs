@@ -1,19 +1,31 @@
 use std::{
     collections::HashMap,
+    env,
     error::Error,
-    fmt::Write,
-    fs::File,
-    hash::Hash,
-    io::BufReader,
+    fs,
+    path::PathBuf,
+    sync::mpsc::{channel, Receiver, Sender},
     time::{Duration, Instant},
 };
 
 use crossterm::event::{self, Event, KeyCode, KeyModifiers};
 use lazy_static::lazy_static;
-use rodio::{source::SineWave, Decoder, OutputStream, OutputStreamHandle, Sink, Source};
-use serde::{Deserialize, Serialize};
+use timers_core::{
+    new_uid,
+    persistence::{unix_now, Autosave, LoadError},
+    LengthChange, SerializeableTimerParts, Timer, TimerEngine, TimerEventListener, TimerSnapshot,
+    TimerState,
+};
 use tui_textarea::{CursorMove, TextArea};
 
+use crate::{
+    import_export::{export_timers, ExchangeFormat},
+    logging::recent_records,
+    presets::Preset,
+    profiles::{list_profiles, DEFAULT_PROFILE},
+    sound::{SoundAlarm, DEFAULT_ALARM_SOUND},
+};
+
 // const EDITFIELD_NEXT: HashMap<EditField, EditField> = HashMap::from();
 
 lazy_static! {
@@ -66,93 +78,61 @@ lazy_static! {
     // ]);
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub enum TimerState {
-    Stopped,
-    Running,
-    Alarming,
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-struct SerializeableTimerParts {
-    length: Duration,
-    time_left: Duration,
-    description: String,
+// Commands sent into the app by integrations running on other threads
+#[derive(Debug)]
+pub enum TimerCommand {
+    Add(SerializeableTimerParts, Sender<TimerSnapshot>),
+    Start(u64),
+    Pause(u64),
+    Reset(u64),
+    // Only sent from desktop notifications
+    #[cfg_attr(not(feature = "dbus"), allow(dead_code))]
+    Acknowledge(u64),
+    #[cfg_attr(not(feature = "dbus"), allow(dead_code))]
+    Snooze(u64),
+    Delete(u64),
+    PauseAll,
+    AcknowledgeAll,
+    // The save file changed on disk, from the timers it had before to these
+    MergeExternal(Vec<SerializeableTimerParts>, Vec<SerializeableTimerParts>),
+    Reload,
+    // A config file changed, so the settings are applied again
+    ReloadSettings,
+    Quit,
 }
 
-impl SerializeableTimerParts {
-    fn new(description: String, length: Duration, time_left: Duration) -> SerializeableTimerParts {
-        SerializeableTimerParts {
-            length,
-            time_left,
-            description,
-        }
-    }
-}
-
-// #[derive(Serialize, Deserialize, Debug)]
-pub struct Timer {
-    // pub length: Duration,
-    // pub time_left: Duration,
-    serializeable_parts: SerializeableTimerParts,
-    last_started: Option<Instant>,
-    time_left_at_last_tick: Duration,
-    pub state: TimerState,
-    // pub title: String,
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub enum SortMode {
+    Manual,
+    TimeLeft,
+    Length,
+    Description,
+    State,
+    NextToFire,
 }
 
-impl Timer {
-    pub fn new(description: String, length: Duration) -> Timer {
-        Timer {
-            // length,
-            // time_left: length,
-            serializeable_parts: SerializeableTimerParts::new(description, length, length),
-            last_started: None,
-            time_left_at_last_tick: length,
-            state: TimerState::Stopped,
-            // title: description,
+impl SortMode {
+    fn next(self) -> SortMode {
+        match self {
+            SortMode::Manual => SortMode::TimeLeft,
+            SortMode::TimeLeft => SortMode::Length,
+            SortMode::Length => SortMode::Description,
+            SortMode::Description => SortMode::State,
+            SortMode::State => SortMode::NextToFire,
+            SortMode::NextToFire => SortMode::Manual,
         }
     }
 
-    pub fn default() -> Timer {
-        let default_duration = Duration::from_secs(300);
-        Timer {
-            // length: default_duration,
-            // time_left: default_duration,
-            serializeable_parts: SerializeableTimerParts::new(
-                String::from("New Timer"),
-                default_duration,
-                default_duration,
-            ),
-            last_started: None,
-            time_left_at_last_tick: default_duration,
-            state: TimerState::Stopped,
-            // title: "New Timer".to_string(),
+    pub fn name(&self) -> &'static str {
+        match self {
+            SortMode::Manual => "manual order",
+            SortMode::TimeLeft => "time left",
+            SortMode::Length => "length",
+            SortMode::Description => "description",
+            SortMode::State => "state",
+            SortMode::NextToFire => "next to fire",
         }
     }
-
-    fn from_serializeable(parts: SerializeableTimerParts) -> Timer {
-        let time_left_at_last_tick = parts.time_left;
-
-        Timer {
-            serializeable_parts: parts,
-            last_started: None,
-            time_left_at_last_tick,
-            state: TimerState::Stopped,
-        }
-    }
-
-    pub fn clone_description(&self) -> String {
-        self.serializeable_parts.description.clone()
-    }
-
-    pub fn get_length(&self) -> Duration {
-        self.serializeable_parts.length
-    }
-
-    pub fn get_time_left(&self) -> Duration {
-        self.serializeable_parts.time_left
-    }
 }
 
 #[derive(PartialEq, Eq, Hash, Clone, Copy)]
@@ -303,250 +283,372 @@ impl EditValues<'_> {
     }
 }
 
+#[allow(dead_code)]
 pub enum ErrorType {
     SoundDevice,
     File,
+    // The save file could not be loaded. It was moved to kept_aside, and the
+    // timers came from the restored backup if there was a good one
+    BrokenSaveFile {
+        reason: String,
+        kept_aside: Option<PathBuf>,
+        restored: Option<PathBuf>,
+    },
+    // Another instance holds the profile switched to, so it is read-only
+    ProfileInUse(String),
+    ExportFailed(String),
+    // The config file has a mistake in it, so its settings were not applied
+    Settings(String),
+    // The timer being edited was deleted before the edit was saved
+    EditedTimerDeleted,
 }
 
+#[allow(dead_code)]
 pub enum AppScreen {
     Main,
     Editing(EditField),
+    LengthChange,
+    Searching,
+    Presets,
+    Profiles,
+    Export,
+    Log,
     Navigating,
     Error(ErrorType),
 }
 
-struct AlarmCounter {
-    alarming_timers: u32,
-    _stream: Option<OutputStream>,
-    _stream_handle: Option<OutputStreamHandle>,
-    alarm_sink: Option<Sink>,
-}
-
-impl AlarmCounter {
-    fn new(filename: &str) -> AlarmCounter {
-        // let (_stream, stream_handle) = OutputStream::try_default().unwrap();
-        //
-        if let Ok((_stream, stream_handle)) = OutputStream::try_default() {
-            if let Ok(sink) = Sink::try_new(&stream_handle) {
-                if let Ok(file) = File::open(filename) {
-                    let file = BufReader::new(file);
-
-                    if let Ok(source) = Decoder::new(file) {
-                        let source = source.repeat_infinite();
-
-                        sink.append(source);
-                        sink.pause();
-
-                        return AlarmCounter {
-                            alarming_timers: 0,
-                            _stream: Some(_stream),
-                            _stream_handle: Some(stream_handle),
-                            alarm_sink: Some(sink),
-                        };
-                    }
-                } else {
-                    let source = SineWave::new(600.0)
-                        .take_duration(Duration::from_millis(1000))
-                        .delay(Duration::from_millis(1000))
-                        .repeat_infinite()
-                        .skip_duration(Duration::from_millis(1000));
-                    // let source = from_iter(
-                    //     SineWave::new(600.0)
-                    //         .take_duration(Duration::from_millis(1000))
-                    //         .chain(
-                    //             SineWave::new(600.0)
-                    //                 .amplify(0.0)
-                    //                 .take_duration(Duration::from_millis(1000)),
-                    //         ),
-                    // )
-                    // .repeat_infinite();
-
-                    // let source = SineWave::new(600.0)
-                    //     .take_duration(Duration::from_millis(1000))
-                    //     .chain(SineWave::new(600.0).take_duration(Duration::from_millis(1000)))
-                    //     .collect::<Source>()
-                    //     // .delay(Duration::from_millis(1000))
-                    //     .repeat_infinite();
-
-                    sink.append(source);
-                    sink.pause();
-
-                    // sink.append(source2);
-
-                    return AlarmCounter {
-                        alarming_timers: 0,
-                        _stream: Some(_stream),
-                        _stream_handle: Some(stream_handle),
-                        alarm_sink: Some(sink),
-                    };
-                }
-            }
-        }
-
-        AlarmCounter {
-            alarming_timers: 0,
-            _stream: None,
-            _stream_handle: None,
-            alarm_sink: None,
-        }
+// How long a toast stays at the top of the screen
+const TOAST_DURATION: Duration = Duration::from_secs(3);
 
-        // let sink = Sink::try_new(&stream_handle).unwrap();
-
-        // let file = BufReader::new(File::open(filename).unwrap());
-
-        // let source = Decoder::new(file).unwrap().repeat_infinite();
-
-        // sink.append(source);
-        // sink.pause();
-
-        // AlarmCounter {
-        //     alarming_timers: 0,
-        //     _stream,
-        //     _stream_handle: stream_handle,
-        //     alarm_sink: sink,
-        // }
-    }
-
-    fn increase_counter(&mut self) {
-        if self.alarming_timers == 0 {
-            // for _ in 1..=10 {
-            //     println!("IT GETS HERE");
-            // }
-            if let Some(sink) = &self.alarm_sink {
-                sink.play()
-            }
-        }
-
-        self.alarming_timers += 1;
-    }
-
-    fn decrease_counter(&mut self) {
-        self.alarming_timers -= 1;
-
-        if self.alarming_timers == 0 {
-            if let Some(sink) = &self.alarm_sink {
-                sink.pause()
-            }
-        }
-    }
-}
+// Groups of listeners on the engine. The profile's are tied to its save file
+// and dropped on switching, the settings' are replaced when they are reloaded
+const APP_LISTENERS: &str = "app";
+const PROFILE_LISTENERS: &str = "profile";
+const SETTINGS_LISTENERS: &str = "settings";
+const AUTOSAVE_LISTENERS: &str = "autosave";
 
 pub struct App<'a> {
-    pub timers: Vec<Timer>,
+    pub engine: TimerEngine,
     pub selected_timer: Option<usize>,
     pub should_quit: bool,
+    pub should_reload: bool,
+    // Set when a config file changed and the settings should be applied
+    pub reload_settings: bool,
     pub screen: AppScreen,
     pub edit_values: EditValues<'a>,
     pub successful_init: bool,
-    alarm_counter: AlarmCounter,
+    pub sort_mode: SortMode,
+    pub search_query: String,
+    last_search: String,
+    pub presets: Vec<Preset>,
+    pub selected_preset: usize,
+    pub profile: String,
+    pub profiles: Vec<String>,
+    pub selected_profile: usize,
+    // How many records the log viewer is scrolled back from the newest
+    pub log_scroll: usize,
+    // Set when the profile switcher picked another profile to open
+    pub switch_profile: Option<String>,
+    // A short message shown at the top until it expires
+    pub toast: Option<(String, Instant)>,
+    // The id of the timer being edited, which stays put while the selection
+    // moves because timers were added or deleted elsewhere
+    edited_timer: Option<u64>,
+    // The sound the alarm was last built from
+    alarm_sound: PathBuf,
+    // An error that came up while another screen was open, shown once the
+    // screen is back on the timers
+    held_error: Option<ErrorType>,
+    command_sender: Sender<TimerCommand>,
+    command_receiver: Receiver<TimerCommand>,
 }
 
 impl App<'_> {
     // Singleton so this is fine
     pub fn new() -> App<'static> {
-        let alarm_counter = AlarmCounter::new("rodio_alarm_test.flac");
+        let (command_sender, command_receiver) = channel();
 
-        let successful_init = alarm_counter.alarm_sink.is_some();
-        App {
-            timers: Vec::new(),
+        let mut app = App {
+            engine: TimerEngine::new(),
             selected_timer: None,
             should_quit: false,
+            should_reload: false,
+            reload_settings: false,
             screen: AppScreen::Main,
             edit_values: EditValues::default(),
-            alarm_counter,
-            successful_init,
-        }
+            successful_init: false,
+            sort_mode: SortMode::Manual,
+            search_query: String::new(),
+            last_search: String::new(),
+            presets: Vec::new(),
+            selected_preset: 0,
+            profile: String::from(DEFAULT_PROFILE),
+            profiles: Vec::new(),
+            selected_profile: 0,
+            log_scroll: 0,
+            switch_profile: None,
+            toast: None,
+            edited_timer: None,
+            alarm_sound: PathBuf::new(),
+            held_error: None,
+            command_sender,
+            command_receiver,
+        };
+
+        app.set_alarm_sound(&None);
+
+        app
     }
 
-    pub fn update_timers(&mut self) {
-        for timer in &mut self.timers {
-            let now = Instant::now();
-            // let t = &mut tb.timer;
+    pub fn add_listener(&mut self, listener: Box<dyn TimerEventListener>) {
+        self.engine.add_listener(APP_LISTENERS, listener);
+    }
 
-            match timer.state {
-                TimerState::Stopped => (),
-                TimerState::Running => {
-                    if let Some(tick) = timer.last_started {
-                        let time_elapsed = now.duration_since(tick);
+    pub fn add_profile_listener(&mut self, listener: Box<dyn TimerEventListener>) {
+        self.engine.add_listener(PROFILE_LISTENERS, listener);
+    }
 
-                        if let Some(new_time_left) =
-                            timer.time_left_at_last_tick.checked_sub(time_elapsed)
-                        {
-                            timer.serializeable_parts.time_left = new_time_left;
-                        } else {
-                            timer.serializeable_parts.time_left = Duration::ZERO;
-                            timer.time_left_at_last_tick = Duration::ZERO;
-                            timer.state = TimerState::Alarming;
-                            // self.alarming_timers += 1;
-                            self.alarm_counter.increase_counter();
-                        }
-                    } else {
-                        timer.last_started = Some(now);
-                    }
-                }
-                TimerState::Alarming => (),
-            }
+    pub fn clear_profile_listeners(&mut self) {
+        self.engine.remove_listeners(PROFILE_LISTENERS);
+    }
+
+    // Replaces the Autosave listener, which follows the autosave settings
+    // rather than the profile
+    pub fn set_autosave(&mut self, autosave: Option<Autosave>) {
+        self.engine.remove_listeners(AUTOSAVE_LISTENERS);
+
+        if let Some(autosave) = autosave {
+            self.engine
+                .add_listener(AUTOSAVE_LISTENERS, Box::new(autosave));
         }
     }
 
-    fn add_default_timer(&mut self) {
-        let new_timer = Timer::default();
+    pub fn add_settings_listener(&mut self, listener: Box<dyn TimerEventListener>) {
+        self.engine.add_listener(SETTINGS_LISTENERS, listener);
+    }
+
+    pub fn clear_settings_listeners(&mut self) {
+        self.engine.remove_listeners(SETTINGS_LISTENERS);
+    }
 
-        // let new_timer_box = TimerBox::new(new_timer);
+    pub fn show_toast(&mut self, message: String) {
+        self.toast = Some((message, Instant::now()));
+    }
 
-        self.timers.push(new_timer);
+    // Shows the error at once on the timers screen. Anywhere else it waits
+    // until the screen is back there, so an edit in progress isn't lost
+    pub fn show_error(&mut self, error: ErrorType) {
+        self.held_error = Some(error);
+        self.show_held_error();
+    }
 
-        self.selected_timer = match self.selected_timer {
-            Some(i) => Some(i),
-            None => Some(0),
+    pub fn show_held_error(&mut self) {
+        if let AppScreen::Main = self.screen {
+            if let Some(error) = self.held_error.take() {
+                self.screen = AppScreen::Error(error);
+            }
         }
     }
 
-    fn add_timer_from_saved(&mut self, deserialized: SerializeableTimerParts) {
-        let new_timer = Timer::from_serializeable(deserialized);
+    // The toast to show, if there is one that hasn't expired
+    pub fn current_toast(&self) -> Option<&str> {
+        match &self.toast {
+            Some((message, shown)) if shown.elapsed() < TOAST_DURATION => Some(message),
+            _ => None,
+        }
+    }
 
-        self.timers.push(new_timer);
+    // Switches the alarm to another sound, rebuilding the audio sink. Timers
+    // that are alarming keep ringing with the new sound. Returns an error to
+    // show if the sound file or the sound device could not be played
+    pub fn set_alarm_sound(&mut self, alarm_sound: &Option<PathBuf>) -> Option<ErrorType> {
+        let sound_file = match alarm_sound {
+            Some(alarm_sound) => alarm_sound.clone(),
+            None => PathBuf::from(DEFAULT_ALARM_SOUND),
+        };
+
+        if sound_file == self.alarm_sound {
+            return None;
+        }
 
-        self.selected_timer = match self.selected_timer {
-            Some(i) => Some(i),
-            None => Some(0),
+        // The old alarm is only replaced once the new one is built, so losing
+        // the sound device leaves the alarm that was working in place
+        let Some(alarm) = SoundAlarm::new(&sound_file) else {
+            return Some(ErrorType::SoundDevice);
+        };
+        let playing_file = alarm.playing_file;
+
+        self.successful_init = true;
+        self.alarm_sound = sound_file;
+        self.engine.set_alarm(Box::new(alarm));
+
+        match alarm_sound {
+            Some(_) if !playing_file => Some(ErrorType::File),
+            _ => None,
         }
     }
 
-    fn delete_timer(&mut self) {
-        match self.selected_timer {
-            None => {}
-            Some(i) => {
-                if let TimerState::Alarming = self.timers[i].state {
-                    self.alarm_counter.decrease_counter()
-                }
-                self.timers.remove(i);
+    pub fn command_sender(&self) -> Sender<TimerCommand> {
+        self.command_sender.clone()
+    }
 
-                self.selected_timer = {
-                    let s = self.timers.len();
+    // Runs something that can add or remove timers, then puts the selection
+    // back on the timer it was on. If that timer is gone, the highlight stays
+    // on the same row of the table
+    fn keep_selection(&mut self, change: impl FnOnce(&mut TimerEngine)) {
+        let selected = self
+            .selected_timer
+            .map(|i| (self.engine.timers()[i].id(), self.visible_position(i)));
 
-                    if s == 0 {
+        change(&mut self.engine);
+
+        self.selected_timer = match selected {
+            Some((id, position)) => match self.engine.index_of(id) {
+                Some(i) => Some(i),
+                None => {
+                    let order = self.visible_timers();
+
+                    if order.is_empty() {
                         None
-                    } else if s == i {
-                        Some(s - 1)
                     } else {
-                        Some(i)
+                        Some(order[position.min(order.len() - 1)])
                     }
                 }
+            },
+            None => None,
+        };
+
+        self.fix_selection();
+    }
+
+    pub fn merge_external(
+        &mut self,
+        base: &[SerializeableTimerParts],
+        new: &[SerializeableTimerParts],
+    ) {
+        self.keep_selection(|engine| engine.merge_external(base, new));
+    }
+
+    pub fn process_commands(&mut self) {
+        while let Ok(command) = self.command_receiver.try_recv() {
+            let (id, action): (u64, fn(&mut TimerEngine, usize)) = match command {
+                TimerCommand::Add(mut parts, reply) => {
+                    parts.uid = new_uid();
+
+                    let mut added = 0;
+                    self.keep_selection(|engine| added = engine.add_timer_from_saved(parts));
+
+                    let _ = reply.send(self.engine.timers()[added].snapshot());
+                    continue;
+                }
+                TimerCommand::Start(id) => (id, TimerEngine::start_timer),
+                TimerCommand::Pause(id) => (id, TimerEngine::pause_timer),
+                TimerCommand::Reset(id) => (id, TimerEngine::reset_timer),
+                TimerCommand::Acknowledge(id) => (id, TimerEngine::acknowledge_timer),
+                TimerCommand::Snooze(id) => (id, TimerEngine::snooze_timer),
+                TimerCommand::Delete(id) => {
+                    if let Some(i) = self.engine.index_of(id) {
+                        self.delete_timer(i);
+                    }
+                    continue;
+                }
+                TimerCommand::PauseAll => {
+                    self.engine.pause_all();
+                    continue;
+                }
+                TimerCommand::AcknowledgeAll => {
+                    self.engine.acknowledge_all();
+                    continue;
+                }
+                TimerCommand::MergeExternal(base, new) => {
+                    self.merge_external(&base, &new);
+                    continue;
+                }
+                TimerCommand::Reload => {
+                    self.should_reload = true;
+                    continue;
+                }
+                TimerCommand::ReloadSettings => {
+                    self.reload_settings = true;
+                    continue;
+                }
+                TimerCommand::Quit => {
+                    self.should_quit = true;
+                    continue;
+                }
+            };
+
+            if let Some(i) = self.engine.index_of(id) {
+                action(&mut self.engine, i);
             }
         }
     }
 
+    fn on_selected(&mut self, action: fn(&mut TimerEngine, usize)) {
+        if let Some(i) = self.selected_timer {
+            action(&mut self.engine, i);
+        }
+    }
+
+    // Indices into timers in the order the table shows them. The selection
+    // always stays an index into timers so it follows a timer when the
+    // order changes
+    pub fn visible_timers(&self) -> Vec<usize> {
+        let timers = self.engine.timers();
+
+        let mut order: Vec<usize> = (0..timers.len())
+            .filter(|&i| fuzzy_match(&self.search_query, timers[i].description()))
+            .collect();
+
+        match self.sort_mode {
+            SortMode::Manual => (),
+            SortMode::TimeLeft => order.sort_by_key(|&i| timers[i].get_time_left()),
+            SortMode::Length => order.sort_by_key(|&i| timers[i].get_length()),
+            SortMode::Description => order.sort_by_key(|&i| timers[i].description().to_lowercase()),
+            SortMode::State => order.sort_by_key(|&i| timers[i].state_rank()),
+            SortMode::NextToFire => {
+                order.sort_by_key(|&i| (timers[i].state_rank(), timers[i].get_time_left()))
+            }
+        }
+
+        order
+    }
+
+    // Drops every timer without emitting events, for replacing them with the
+    // saved ones
+    pub fn remove_all_timers(&mut self) {
+        self.engine.remove_all_timers();
+        self.selected_timer = None;
+    }
+
+    fn delete_timer(&mut self, i: usize) {
+        self.keep_selection(|engine| engine.delete_timer(i));
+    }
+
+    fn delete_selected_timer(&mut self) {
+        if let Some(i) = self.selected_timer {
+            self.delete_timer(i);
+        }
+    }
+
+    pub fn visible_position(&self, i: usize) -> usize {
+        self.visible_timers()
+            .iter()
+            .position(|&j| j == i)
+            .unwrap_or(0)
+    }
+
     fn increment_selection(&mut self) {
         self.selected_timer = match self.selected_timer {
             Some(i) => {
-                let mut n = i + 1;
+                let order = self.visible_timers();
+                let mut n = self.visible_position(i) + 1;
 
-                if n >= self.timers.len() {
+                if n >= order.len() {
                     n = 0;
                 }
 
-                Some(n)
+                Some(order[n])
             }
             None => None,
         }
@@ -554,123 +656,313 @@ impl App<'_> {
 
     fn decrement_selection(&mut self) {
         self.selected_timer = match self.selected_timer {
-            Some(i) => match i {
-                0 => Some(self.timers.len() - 1),
-                _ => Some(i - 1),
-            },
+            Some(i) => {
+                let order = self.visible_timers();
+
+                match self.visible_position(i) {
+                    0 => Some(order[order.len() - 1]),
+                    n => Some(order[n - 1]),
+                }
+            }
             None => None,
         }
     }
 
-    fn start_timer(&mut self) {
-        if let Some(i) = self.selected_timer {
-            match self.timers[i].state {
-                TimerState::Stopped => {
-                    self.timers[i].state = TimerState::Running;
-                    self.timers[i].last_started = Some(Instant::now());
-                }
-                TimerState::Running => (),
-                TimerState::Alarming => (),
+    // Make sure the selection points at a timer the table is showing, so
+    // actions never hit a timer hidden by the search filter
+    fn fix_selection(&mut self) {
+        let order = self.visible_timers();
+
+        self.selected_timer = match self.selected_timer {
+            Some(i) if order.contains(&i) => Some(i),
+            _ => order.first().copied(),
+        }
+    }
+
+    fn start_search(&mut self) {
+        self.screen = AppScreen::Searching;
+    }
+
+    fn update_search(&mut self, key: KeyCode) {
+        match key {
+            KeyCode::Char(c) => self.search_query.push(c),
+            KeyCode::Backspace => {
+                self.search_query.pop();
             }
+            _ => (),
         }
+
+        self.fix_selection();
     }
 
-    fn pause_timer(&mut self) {
-        if let Some(i) = self.selected_timer {
-            match self.timers[i].state {
-                TimerState::Running => {
-                    self.timers[i].state = TimerState::Stopped;
-                    self.timers[i].last_started = Some(Instant::now());
-                    self.timers[i].time_left_at_last_tick =
-                        self.timers[i].serializeable_parts.time_left;
-                }
-                TimerState::Alarming => {
-                    // Change this! todo!()
-                    // self.alarming_timers -= 1;
-                    self.alarm_counter.decrease_counter();
-                    self.timers[i].state = TimerState::Stopped;
-                    self.timers[i].last_started = None;
-                    self.timers[i].serializeable_parts.time_left =
-                        self.timers[i].serializeable_parts.length;
-                    self.timers[i].time_left_at_last_tick =
-                        self.timers[i].serializeable_parts.length;
+    // Shows every timer again, keeping the query for n/N
+    fn drop_search_filter(&mut self) {
+        if !self.search_query.is_empty() {
+            self.last_search = std::mem::take(&mut self.search_query);
+        }
+    }
+
+    fn clear_search(&mut self) {
+        self.drop_search_filter();
+        self.screen = AppScreen::Main;
+
+        self.fix_selection();
+    }
+
+    // Selects the next timer in the table matching the search. While the
+    // filter is on that is every row, but after Esc has cleared it this skips
+    // the timers that don't match
+    fn select_match(&mut self, forward: bool) {
+        let query = if self.search_query.is_empty() {
+            &self.last_search
+        } else {
+            &self.search_query
+        };
+
+        let i = match self.selected_timer {
+            Some(i) if !query.is_empty() => i,
+            _ => return,
+        };
+
+        let order = self.visible_timers();
+        let timers = self.engine.timers();
+        let position = self.visible_position(i);
+        let len = order.len();
+
+        let found = (1..=len)
+            .map(|step| {
+                if forward {
+                    order[(position + step) % len]
+                } else {
+                    order[(position + len - step) % len]
                 }
-                TimerState::Stopped => (),
-            }
+            })
+            .find(|&j| fuzzy_match(query, timers[j].description()));
+
+        if let Some(j) = found {
+            self.selected_timer = Some(j);
         }
     }
 
-    fn toggle_timer(&mut self) {
+    // Swaps with the neighbour in the table rather than in timers, so with a
+    // search filter on the move is never with a hidden timer
+    fn move_timer_up(&mut self) {
         if let Some(i) = self.selected_timer {
-            match self.timers[i].state {
-                TimerState::Running => self.pause_timer(),
-                TimerState::Stopped => self.start_timer(),
-                // Don't need the alarming branch for pause because nothing
-                // actually calls it?
-                TimerState::Alarming => (),
+            // Moving only makes sense in the saved order, so show it
+            self.sort_mode = SortMode::Manual;
+
+            let order = self.visible_timers();
+
+            if let Some(&j) = self
+                .visible_position(i)
+                .checked_sub(1)
+                .and_then(|n| order.get(n))
+            {
+                self.engine.swap_timers(i, j);
+                self.selected_timer = Some(j);
             }
         }
     }
 
-    fn reset_timer(&mut self) {
+    fn move_timer_down(&mut self) {
         if let Some(i) = self.selected_timer {
-            match self.timers[i].state {
-                TimerState::Running => (),
-                TimerState::Stopped => {
-                    // self.timers[i].state = TimerState::Stopped;
-                    self.timers[i].serializeable_parts.time_left =
-                        self.timers[i].serializeable_parts.length;
-                    self.timers[i].time_left_at_last_tick =
-                        self.timers[i].serializeable_parts.length;
-                    self.timers[i].last_started = None;
-                }
-                // Change app alarming state here!
-                TimerState::Alarming => {
-                    // self.alarming_timers -= 1;
-                    self.alarm_counter.decrease_counter();
-                    self.timers[i].state = TimerState::Stopped;
-                    self.timers[i].last_started = None;
-                    self.timers[i].serializeable_parts.time_left =
-                        self.timers[i].serializeable_parts.length;
-                    self.timers[i].time_left_at_last_tick =
-                        self.timers[i].serializeable_parts.length;
-                }
+            self.sort_mode = SortMode::Manual;
+
+            let order = self.visible_timers();
+
+            if let Some(&j) = order.get(self.visible_position(i) + 1) {
+                self.engine.swap_timers(i, j);
+                self.selected_timer = Some(j);
             }
         }
     }
 
+    fn cycle_sort_mode(&mut self) {
+        self.sort_mode = self.sort_mode.next();
+    }
+
     fn edit_timer(&mut self) {
         if let Some(i) = self.selected_timer {
             self.screen = AppScreen::Editing(EditField::Description);
-            self.edit_values = EditValues::new(
-                self.timers[i].serializeable_parts.description.clone(),
-                self.timers[i].serializeable_parts.length,
-            )
+            let timer = &self.engine.timers()[i];
+
+            self.edited_timer = Some(timer.id());
+            self.edit_values = EditValues::new(timer.clone_description(), timer.get_length())
         }
     }
 
+    // Where the timer being edited is now. If it was deleted while the edit
+    // was open, the edit is dropped rather than landing on another timer
+    fn edited_timer_index(&mut self) -> Option<usize> {
+        let i = self.engine.index_of(self.edited_timer?);
+
+        if i.is_none() {
+            self.screen = AppScreen::Error(ErrorType::EditedTimerDeleted);
+        }
+
+        i
+    }
+
     fn replace_timer(&mut self) {
-        let new_timer = Timer::new(
-            self.edit_values.descript.clone().into_lines().join(""),
-            self.edit_values.to_duration(),
-        );
+        self.screen = AppScreen::Main;
 
-        if let Some(i) = self.selected_timer {
-            if let TimerState::Alarming = self.timers[i].state {
-                self.alarm_counter.decrease_counter()
+        if let Some(i) = self.edited_timer_index() {
+            let length = self.edit_values.to_duration();
+
+            self.engine
+                .set_description(i, self.edit_values.descript.clone().into_lines().join(""));
+
+            let timer = &self.engine.timers()[i];
+
+            if length != timer.get_length() {
+                match timer.state() {
+                    // Acknowledged by the engine before it changes
+                    TimerState::Alarming => {
+                        self.engine.change_length(i, length, LengthChange::Restart)
+                    }
+                    _ => {
+                        if timer.get_time_left() == timer.get_length() {
+                            self.engine.change_length(i, length, LengthChange::Restart);
+                        } else {
+                            // The timer has made progress, so ask what to do with it
+                            self.screen = AppScreen::LengthChange;
+                        }
+                    }
+                }
             }
-            self.timers[i] = new_timer;
         }
+
+        // The new description might not match the search filter any more
+        if let AppScreen::Main = self.screen {
+            self.fix_selection();
+        }
+    }
+
+    fn change_timer_length(&mut self, change: LengthChange) {
+        self.screen = AppScreen::Main;
+
+        if let Some(i) = self.edited_timer_index() {
+            let length = self.edit_values.to_duration();
+
+            self.engine.change_length(i, length, change);
+        }
+
+        self.fix_selection();
     }
 
     fn add_new_timer(&mut self) {
-        self.add_default_timer();
-        self.selected_timer = Some(self.timers.len() - 1);
+        if self.presets.is_empty() {
+            self.add_custom_timer();
+        } else {
+            self.selected_preset = 0;
+            self.screen = AppScreen::Presets;
+        }
+    }
+
+    // Writes every timer, with its current state, to a new file in the
+    // working directory
+    fn export_timers(&mut self, format: ExchangeFormat) {
+        let snapshots = self.engine.snapshots();
+
+        let written_at = unix_now().as_secs();
+        let path = env::current_dir().unwrap_or_default().join(format!(
+            "timers_tui-export-{}.{}",
+            written_at,
+            format.extension()
+        ));
+
+        let result = export_timers(&snapshots, format)
+            .and_then(|exported| fs::write(&path, exported).map_err(|error| error.to_string()));
+
+        match result {
+            Ok(()) => {
+                self.screen = AppScreen::Main;
+                self.show_toast(format!("Exported the timers to {}", path.display()));
+            }
+            Err(error) => self.screen = AppScreen::Error(ErrorType::ExportFailed(error)),
+        }
+    }
+
+    fn open_profiles(&mut self) {
+        self.profiles = list_profiles();
+
+        // A new profile from --profile has no directory until it is saved
+        if !self.profiles.contains(&self.profile) {
+            self.profiles.push(self.profile.clone());
+        }
+
+        self.selected_profile = self
+            .profiles
+            .iter()
+            .position(|profile| *profile == self.profile)
+            .unwrap_or(0);
+        self.screen = AppScreen::Profiles;
+    }
+
+    fn increment_profile_selection(&mut self) {
+        self.selected_profile = (self.selected_profile + 1) % self.profiles.len();
+    }
+
+    fn decrement_profile_selection(&mut self) {
+        self.selected_profile =
+            (self.selected_profile + self.profiles.len() - 1) % self.profiles.len();
+    }
+
+    fn open_log(&mut self) {
+        self.log_scroll = 0;
+        self.screen = AppScreen::Log;
+    }
+
+    fn scroll_log_back(&mut self, records: usize) {
+        let oldest = recent_records().len().saturating_sub(1);
+
+        self.log_scroll = self.log_scroll.saturating_add(records).min(oldest);
+    }
+
+    fn choose_profile(&mut self) {
+        self.screen = AppScreen::Main;
+
+        let profile = &self.profiles[self.selected_profile];
+
+        if *profile != self.profile {
+            self.switch_profile = Some(profile.clone());
+            self.should_reload = true;
+        }
+    }
+
+    fn add_custom_timer(&mut self) {
+        // The new timer might not match the filter, so drop it
+        self.drop_search_filter();
+
+        let i = self.engine.push_timer(Timer::default());
+
+        self.selected_timer = Some(i);
+        self.edited_timer = Some(self.engine.timers()[i].id());
 
         self.screen = AppScreen::Editing(EditField::Description);
         self.edit_values = EditValues::default();
     }
 
+    fn add_timer_from_preset(&mut self, i: usize) {
+        if let Some(preset) = self.presets.get(i) {
+            let new_timer = Timer::new(preset.description.clone(), preset.length);
+
+            self.drop_search_filter();
+
+            self.selected_timer = Some(self.engine.push_timer(new_timer));
+
+            self.screen = AppScreen::Main;
+        }
+    }
+
+    fn increment_preset_selection(&mut self) {
+        self.selected_preset = (self.selected_preset + 1) % self.presets.len();
+    }
+
+    fn decrement_preset_selection(&mut self) {
+        self.selected_preset = (self.selected_preset + self.presets.len() - 1) % self.presets.len();
+    }
+
     pub fn handle_events(&mut self) -> Result<(), Box<dyn Error>> {
         if event::poll(Duration::from_millis(250))? {
             if let Event::Key(key) = event::read()? {
@@ -682,12 +974,69 @@ impl App<'_> {
                             KeyCode::Char('k') => self.decrement_selection(),
                             KeyCode::Char('j') => self.increment_selection(),
                             KeyCode::Char('a') => self.add_new_timer(),
-                            KeyCode::Char('d') => self.delete_timer(),
-                            KeyCode::Char('p') => self.toggle_timer(),
-                            KeyCode::Char('r') => self.reset_timer(),
+                            KeyCode::Char('d') => self.delete_selected_timer(),
+                            KeyCode::Char('p') => self.on_selected(TimerEngine::toggle_timer),
+                            KeyCode::Char('r') => self.on_selected(TimerEngine::reset_timer),
+                            KeyCode::Char('z') => self.on_selected(TimerEngine::snooze_timer),
+                            KeyCode::Char('w') => self.on_selected(TimerEngine::toggle_webhooks),
                             KeyCode::Char('e') => self.edit_timer(),
+                            KeyCode::Char('K') => self.move_timer_up(),
+                            KeyCode::Char('J') => self.move_timer_down(),
+                            KeyCode::Char('s') => self.cycle_sort_mode(),
+                            KeyCode::Char('P') => self.open_profiles(),
+                            KeyCode::Char('x') => self.screen = AppScreen::Export,
+                            KeyCode::Char('L') => self.open_log(),
+                            KeyCode::Char('/') => self.start_search(),
+                            KeyCode::Char('n') => self.select_match(true),
+                            KeyCode::Char('N') => self.select_match(false),
+                            KeyCode::Esc => self.clear_search(),
+                            _ => (),
+                        },
+                        AppScreen::Export => match key.code {
+                            KeyCode::Char('c') => self.export_timers(ExchangeFormat::Csv),
+                            KeyCode::Char('t') => self.export_timers(ExchangeFormat::Toml),
+                            KeyCode::Char('y') => self.export_timers(ExchangeFormat::Yaml),
+                            KeyCode::Esc => self.screen = AppScreen::Main,
+                            _ => (),
+                        },
+                        AppScreen::Log => match key.code {
+                            KeyCode::Char('k') => self.scroll_log_back(1),
+                            KeyCode::Char('j') => {
+                                self.log_scroll = self.log_scroll.saturating_sub(1)
+                            }
+                            KeyCode::Char('g') => self.scroll_log_back(usize::MAX),
+                            KeyCode::Char('G') => self.log_scroll = 0,
+                            KeyCode::Esc | KeyCode::Char('q') => self.screen = AppScreen::Main,
+                            _ => (),
+                        },
+                        AppScreen::Profiles => match key.code {
+                            KeyCode::Char('j') => self.increment_profile_selection(),
+                            KeyCode::Char('k') => self.decrement_profile_selection(),
+                            KeyCode::Enter => self.choose_profile(),
+                            KeyCode::Esc => self.screen = AppScreen::Main,
                             _ => (),
                         },
+                        AppScreen::Presets => match key.code {
+                            KeyCode::Char('j') => self.increment_preset_selection(),
+                            KeyCode::Char('k') => self.decrement_preset_selection(),
+                            KeyCode::Enter => self.add_timer_from_preset(self.selected_preset),
+                            KeyCode::Char('c') => self.add_custom_timer(),
+                            KeyCode::Esc => self.screen = AppScreen::Main,
+                            KeyCode::Char(x) => {
+                                if let Some(x) = x.to_digit(10) {
+                                    // Presets are numbered from 1 in the picker
+                                    if x > 0 {
+                                        self.add_timer_from_preset(x as usize - 1);
+                                    }
+                                }
+                            }
+                            _ => (),
+                        },
+                        AppScreen::Searching => match key.code {
+                            KeyCode::Enter => self.screen = AppScreen::Main,
+                            KeyCode::Esc => self.clear_search(),
+                            _ => self.update_search(key.code),
+                        },
 
                         AppScreen::Editing(edit_field) => match edit_field {
                             EditField::Description => match key.modifiers {
@@ -703,10 +1052,7 @@ impl App<'_> {
                                     KeyCode::BackTab => {
                                         self.screen = AppScreen::Editing(EditField::Seconds2)
                                     }
-                                    KeyCode::Enter => {
-                                        self.replace_timer();
-                                        self.screen = AppScreen::Main;
-                                    }
+                                    KeyCode::Enter => self.replace_timer(),
                                     _ => {
                                         self.edit_values.descript.input(key);
                                     } // _ => todo!(),
@@ -740,10 +1086,7 @@ impl App<'_> {
                                 KeyCode::Char('j') => {
                                     self.edit_values.get_field(edit_field).dec_value()
                                 }
-                                KeyCode::Enter => {
-                                    self.replace_timer();
-                                    self.screen = AppScreen::Main;
-                                }
+                                KeyCode::Enter => self.replace_timer(),
                                 KeyCode::Char(x) => {
                                     if let Some(x) = x.to_digit(10) {
                                         self.edit_values.get_field(edit_field).set_value(x.into());
@@ -754,6 +1097,20 @@ impl App<'_> {
                                 _ => (),
                             },
                         },
+                        AppScreen::LengthChange => match key.code {
+                            KeyCode::Char('e') => {
+                                self.change_timer_length(LengthChange::KeepElapsed)
+                            }
+                            KeyCode::Char('r') => {
+                                self.change_timer_length(LengthChange::KeepRemaining)
+                            }
+                            KeyCode::Char('s') => self.change_timer_length(LengthChange::Restart),
+                            KeyCode::Esc => {
+                                self.screen = AppScreen::Main;
+                                self.fix_selection();
+                            }
+                            _ => (),
+                        },
                         AppScreen::Navigating => todo!(),
                         AppScreen::Error(_) => match key.code {
                             KeyCode::Char('q') => self.should_quit = true,
@@ -767,39 +1124,23 @@ impl App<'_> {
         Ok(())
     }
 
-    pub fn dump_json(&self) -> String {
-        let mut json = String::new();
-
-        let mut timers_slice = Vec::with_capacity(self.timers.len());
-
-        for timer in &self.timers {
-            // write!(
-            //     &mut json,
-            //     "{},\n",
-            //     serde_json::to_string_pretty(&timer.serializeable_parts).unwrap()
-            // )
-            // .unwrap();
-            timers_slice.push(&timer.serializeable_parts);
-        }
-
-        write!(
-            &mut json,
-            "{}",
-            serde_json::to_string_pretty(&timers_slice).unwrap()
-        )
-        .unwrap();
+    // Loads saved timers after the ones already there
+    pub fn read_from_json(&mut self, json: &str) -> Result<(), LoadError> {
+        self.engine.read_from_json(json)?;
+        self.fix_selection();
 
-        json
+        Ok(())
     }
+}
 
-    pub fn read_from_json(&mut self, json: &str) -> Result<(), serde_json::Error> {
-        // let timers: Vec<SerializeableTimerParts> = serde_json::from_str(&json).unwrap();
-        let timers: Vec<SerializeableTimerParts> = serde_json::from_str(json)?;
+// Case insensitive subsequence match, so "tea" matches "Green Tea" and
+// "gtea" does too
+fn fuzzy_match(query: &str, text: &str) -> bool {
+    let mut text = text.chars().flat_map(char::to_lowercase);
 
-        for timer in timers {
-            self.add_timer_from_saved(timer);
-        }
-
-        Ok(())
-    }
+    query
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .flat_map(char::to_lowercase)
+        .all(|q| text.any(|t| t == q))
 }
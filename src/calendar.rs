@@ -0,0 +1,187 @@
+use std::{
+    fs,
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use timers_core::{
+    change_key, persistence::write_atomically, TimerEvent, TimerEventListener, TimerSnapshot,
+    TimerState,
+};
+
+use crate::ui::format_duration;
+
+// Keeps an iCalendar file with an event and an alarm for when each running
+// timer will go off, for calendar apps to subscribe to. Paused and stopped
+// timers have no fire time, so they are left out
+pub struct CalendarExporter {
+    path: PathBuf,
+    last_change_key: String,
+}
+
+impl CalendarExporter {
+    pub fn new(path: PathBuf) -> CalendarExporter {
+        if let Some(parent) = path.parent() {
+            let _ = fs::create_dir_all(parent);
+        }
+
+        CalendarExporter {
+            path,
+            last_change_key: String::new(),
+        }
+    }
+
+    fn write(&self, timers: &[TimerSnapshot]) -> bool {
+        let now = SystemTime::now();
+
+        let events: Vec<String> = timers
+            .iter()
+            .filter(|timer| matches!(timer.state, TimerState::Running))
+            .map(|timer| event(timer, now))
+            .collect();
+
+        write_atomically(&self.path, &calendar(&events)).is_ok()
+    }
+}
+
+// The UTC year, month, day and seconds into the day of a time
+pub fn utc_date(time: SystemTime) -> (i64, i64, i64, i64) {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64;
+
+    let days = seconds.div_euclid(86400);
+    let time_of_day = seconds.rem_euclid(86400);
+
+    // Days since the epoch to a civil date, from Howard Hinnant's algorithms
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day, time_of_day)
+}
+
+// Dates as the UTC form iCalendar wants, e.g. 20261018T093000Z
+fn format_utc(time: SystemTime) -> String {
+    let (year, month, day, time_of_day) = utc_date(time);
+
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        year,
+        month,
+        day,
+        time_of_day / 3600,
+        time_of_day / 60 % 60,
+        time_of_day % 60
+    )
+}
+
+fn escape_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+// Lines longer than 75 octets are folded onto continuation lines starting
+// with a space
+fn fold_line(line: &str) -> String {
+    let mut folded = String::new();
+    let mut length = 0;
+
+    for c in line.chars() {
+        if length + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            length = 1;
+        }
+
+        folded.push(c);
+        length += c.len_utf8();
+    }
+
+    folded.push_str("\r\n");
+
+    folded
+}
+
+fn event(timer: &TimerSnapshot, now: SystemTime) -> String {
+    // Whole seconds, so the file doesn't change with every tick
+    let time_left = Duration::from_secs(timer.parts.time_left.as_secs());
+    let fires_at = format_utc(now + time_left);
+
+    [
+        String::from("BEGIN:VEVENT"),
+        format!("UID:{}@timers_tui", timer.parts.uid),
+        format!("DTSTAMP:{}", format_utc(now)),
+        format!("DTSTART:{}", fires_at),
+        format!("DTEND:{}", fires_at),
+        format!("SUMMARY:⏰ {}", escape_text(&timer.parts.description)),
+        format!(
+            "DESCRIPTION:{} timer from timers_tui",
+            format_duration(timer.parts.length)
+        ),
+        String::from("BEGIN:VALARM"),
+        String::from("ACTION:DISPLAY"),
+        String::from("TRIGGER:PT0S"),
+        format!("DESCRIPTION:{}", escape_text(&timer.parts.description)),
+        String::from("END:VALARM"),
+        String::from("END:VEVENT"),
+    ]
+    .iter()
+    .map(|line| fold_line(line))
+    .collect()
+}
+
+fn calendar(events: &[String]) -> String {
+    let mut calendar = String::new();
+
+    for line in [
+        "BEGIN:VCALENDAR",
+        "VERSION:2.0",
+        &format!(
+            "PRODID:-//timers_tui//timers_tui {}//EN",
+            env!("CARGO_PKG_VERSION")
+        ),
+        "CALSCALE:GREGORIAN",
+        "X-WR-CALNAME:Timers",
+    ] {
+        calendar.push_str(&fold_line(line));
+    }
+
+    for event in events {
+        calendar.push_str(event);
+    }
+
+    calendar.push_str(&fold_line("END:VCALENDAR"));
+
+    calendar
+}
+
+impl TimerEventListener for CalendarExporter {
+    fn on_event(&mut self, _event: &TimerEvent) {}
+
+    fn on_update(&mut self, timers: &[TimerSnapshot]) {
+        // Running timers count down in step with the clock, so their fire
+        // times stay put
+        let key = change_key(timers);
+
+        if key != self.last_change_key && self.write(timers) {
+            self.last_change_key = key;
+        }
+    }
+}
+
+impl Drop for CalendarExporter {
+    fn drop(&mut self) {
+        // Timers stop when the TUI exits, so nothing will go off
+        self.write(&[]);
+    }
+}
@@ -0,0 +1,150 @@
+use std::{
+    backtrace::Backtrace,
+    fs,
+    io::{self, Write},
+    panic::{self, PanicHookInfo},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use crossterm::{
+    cursor::Show,
+    event::DisableMouseCapture,
+    execute,
+    terminal::{disable_raw_mode, LeaveAlternateScreen},
+};
+use log::error;
+
+use timers_core::{
+    persistence::{saved_timers_json, unix_now},
+    SerializeableTimerParts, TimerEvent, TimerEventListener, TimerSnapshot,
+};
+
+use crate::paths::ensure_state_dir;
+
+// Keeps a copy of the timers the panic hook can still reach once the app
+// itself is unreachable
+pub struct CrashGuard {
+    timers: Arc<Mutex<Vec<SerializeableTimerParts>>>,
+}
+
+impl TimerEventListener for CrashGuard {
+    fn on_event(&mut self, _event: &TimerEvent) {}
+
+    fn on_update(&mut self, timers: &[TimerSnapshot]) {
+        if let Ok(mut saved) = self.timers.lock() {
+            *saved = timers.iter().map(|timer| timer.parts.clone()).collect();
+        }
+    }
+}
+
+// The save file of the profile in use, which the emergency copy goes next to
+static EMERGENCY_SAVE_FILE: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+pub fn set_emergency_save_file(save_file: Option<PathBuf>) {
+    if let Ok(mut emergency_save_file) = EMERGENCY_SAVE_FILE.lock() {
+        *emergency_save_file = save_file;
+    }
+}
+
+// Next to the save file, so it is easy to find and copy back over it
+fn emergency_path(save_file: &Option<PathBuf>) -> Option<PathBuf> {
+    match save_file {
+        Some(save_file) => Some(save_file.with_extension("emergency.json")),
+        None => Some(ensure_state_dir()?.join("emergency_timers.json")),
+    }
+}
+
+fn write_emergency_copy(
+    timers: &Mutex<Vec<SerializeableTimerParts>>,
+    save_file: &Option<PathBuf>,
+) -> Option<PathBuf> {
+    // The lock is only held while copying snapshots, but never wait on it
+    // from inside a panic
+    let timers = timers.try_lock().ok()?;
+    let json = saved_timers_json(timers.iter());
+    let path = emergency_path(save_file)?;
+
+    fs::write(&path, json).ok()?;
+
+    Some(path)
+}
+
+fn write_crash_report(info: &PanicHookInfo) -> Option<PathBuf> {
+    let path = ensure_state_dir()?.join(format!("crash-{}.txt", unix_now().as_secs()));
+
+    let report = format!(
+        "timers_tui {} panicked on thread '{}'\n\n{}\n\n{}\n",
+        env!("CARGO_PKG_VERSION"),
+        thread::current().name().unwrap_or("<unnamed>"),
+        info,
+        Backtrace::force_capture(),
+    );
+
+    fs::write(&path, report).ok()?;
+
+    Some(path)
+}
+
+// Installs a panic hook that gives the terminal back and saves what it can.
+// A panic on the main thread is fatal, so the terminal is restored and the
+// timers are written out before the usual message. Worker threads only get
+// a crash report and a log record, since the TUI is still drawing
+pub fn install_panic_hook(save_file: Option<PathBuf>) -> CrashGuard {
+    set_emergency_save_file(save_file);
+
+    let timers = Arc::new(Mutex::new(Vec::new()));
+    let hook_timers = Arc::clone(&timers);
+    let default_hook = panic::take_hook();
+
+    panic::set_hook(Box::new(move |info| {
+        let crash_report = write_crash_report(info);
+
+        // Writing to stderr would draw over the TUI, so it goes to the log
+        if thread::current().name() != Some("main") {
+            error!(
+                "Thread '{}' panicked: {}{}",
+                thread::current().name().unwrap_or("<unnamed>"),
+                info,
+                match &crash_report {
+                    Some(path) => format!(", a crash report was written to {}", path.display()),
+                    None => String::new(),
+                }
+            );
+            return;
+        }
+
+        let _ = disable_raw_mode();
+        let _ = execute!(
+            io::stderr(),
+            LeaveAlternateScreen,
+            DisableMouseCapture,
+            Show
+        );
+
+        default_hook(info);
+
+        let mut stderr = io::stderr();
+
+        let save_file = match EMERGENCY_SAVE_FILE.try_lock() {
+            Ok(save_file) => save_file.clone(),
+            Err(_) => None,
+        };
+
+        match write_emergency_copy(&hook_timers, &save_file) {
+            Some(path) => {
+                let _ = writeln!(stderr, "Timers were saved to {}", path.display());
+            }
+            None => {
+                let _ = writeln!(stderr, "Could not save an emergency copy of the timers");
+            }
+        }
+
+        if let Some(path) = crash_report {
+            let _ = writeln!(stderr, "A crash report was written to {}", path.display());
+        }
+    }));
+
+    CrashGuard { timers }
+}
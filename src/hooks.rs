@@ -0,0 +1,98 @@
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+    thread,
+};
+
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+use timers_core::{TimerEvent, TimerEventKind, TimerEventListener};
+
+// Shell commands from the [hooks] table of the config file. Each one is run
+// with `sh -c`, gets the timer details in TIMER_* environment variables and
+// the whole event as JSON on stdin
+#[derive(Deserialize, Serialize, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Hooks {
+    start: Option<String>,
+    pause: Option<String>,
+    alarm: Option<String>,
+    acknowledge: Option<String>,
+    snooze: Option<String>,
+    delete: Option<String>,
+}
+
+impl Hooks {
+    pub fn is_empty(&self) -> bool {
+        self.start.is_none()
+            && self.pause.is_none()
+            && self.alarm.is_none()
+            && self.acknowledge.is_none()
+            && self.snooze.is_none()
+            && self.delete.is_none()
+    }
+
+    fn command_for(&self, kind: TimerEventKind) -> Option<&String> {
+        match kind {
+            TimerEventKind::Started => self.start.as_ref(),
+            TimerEventKind::Paused => self.pause.as_ref(),
+            TimerEventKind::Alarmed => self.alarm.as_ref(),
+            TimerEventKind::Acknowledged => self.acknowledge.as_ref(),
+            TimerEventKind::Snoozed => self.snooze.as_ref(),
+            TimerEventKind::Deleted => self.delete.as_ref(),
+        }
+    }
+}
+
+fn run_hook(command: &str, event: &TimerEvent) {
+    let json = serde_json::to_string(event).unwrap_or_default();
+
+    let child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .env("TIMER_EVENT", event.kind.name())
+        .env("TIMER_ID", event.id.to_string())
+        .env("TIMER_INDEX", event.index.to_string())
+        .env("TIMER_DESCRIPTION", &event.description)
+        .env("TIMER_LENGTH", event.length.as_secs().to_string())
+        .env("TIMER_TIME_LEFT", event.time_left.as_secs().to_string())
+        .env("TIMER_OVERTIME", event.overtime.as_secs().to_string())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+
+    let mut child = match child {
+        Ok(child) => child,
+        Err(error) => {
+            warn!("Could not run the {} hook: {}", event.kind.name(), error);
+            return;
+        }
+    };
+
+    debug!("Running the {} hook: {}", event.kind.name(), command);
+
+    let kind = event.kind.name();
+
+    // Feed stdin and reap the child off the event loop
+    thread::spawn(move || {
+        if let Some(mut stdin) = child.stdin.take() {
+            let _ = stdin.write_all(json.as_bytes());
+        }
+
+        match child.wait() {
+            Ok(status) if !status.success() => warn!("The {} hook failed with {}", kind, status),
+            Ok(_) => (),
+            Err(error) => warn!("Could not wait for the {} hook: {}", kind, error),
+        }
+    });
+}
+
+impl TimerEventListener for Hooks {
+    fn on_event(&mut self, event: &TimerEvent) {
+        if let Some(command) = self.command_for(event.kind) {
+            run_hook(command, event);
+        }
+    }
+}
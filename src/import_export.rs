@@ -0,0 +1,584 @@
+use std::{
+    fmt::{self, Display},
+    fs,
+    io::{self, ErrorKind, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use clap::ValueEnum;
+use serde::{
+    de::{DeserializeSeed, IgnoredAny, MapAccess, SeqAccess, Visitor},
+    Deserialize, Deserializer, Serialize,
+};
+use toml::Spanned;
+
+use timers_core::{
+    new_uid,
+    persistence::{parse_saved_timers, save_timers_file, saved_timers_json},
+    SerializeableTimerParts, TimerSnapshot, TimerState,
+};
+
+use crate::{presets::parse_length, ui::format_duration};
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ExchangeFormat {
+    /// description, length, remaining and state columns with a header row
+    Csv,
+    /// A [[timers]] table per timer
+    Toml,
+    /// A timers list
+    Yaml,
+}
+
+impl ExchangeFormat {
+    pub fn from_path(path: &Path) -> Option<ExchangeFormat> {
+        match path.extension()?.to_str()?.to_lowercase().as_str() {
+            "csv" => Some(ExchangeFormat::Csv),
+            "toml" => Some(ExchangeFormat::Toml),
+            "yaml" | "yml" => Some(ExchangeFormat::Yaml),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExchangeFormat::Csv => "csv",
+            ExchangeFormat::Toml => "toml",
+            ExchangeFormat::Yaml => "yaml",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum ImportMode {
+    /// Update timers with the same description and add the rest
+    Merge,
+    /// Drop the existing timers
+    Replace,
+}
+
+// One timer as it is written out, with lengths as H:MM:SS so the files are
+// easy to edit by hand
+#[derive(Serialize, Deserialize)]
+struct TimerRecord {
+    description: String,
+    length: String,
+    #[serde(default)]
+    remaining: Option<String>,
+    #[serde(default)]
+    state: Option<String>,
+}
+
+#[derive(Serialize)]
+struct TimerRecords {
+    timers: Vec<TimerRecord>,
+}
+
+#[derive(Deserialize)]
+struct TomlTimers {
+    timers: Vec<Spanned<TimerRecord>>,
+}
+
+#[derive(Deserialize)]
+struct YamlTimers {
+    timers: Vec<TimerRecord>,
+}
+
+pub struct RowError {
+    pub line: usize,
+    pub message: String,
+}
+
+pub enum ImportError {
+    // The file as a whole could not be read
+    Parse(String),
+    // Every row that is wrong, so they can all be fixed in one go
+    Rows(Vec<RowError>),
+}
+
+impl Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportError::Parse(message) => write!(f, "{}", message),
+            ImportError::Rows(errors) => {
+                let lines: Vec<String> = errors
+                    .iter()
+                    .map(|error| format!("line {}: {}", error.line, error.message))
+                    .collect();
+
+                write!(f, "{}", lines.join("\n"))
+            }
+        }
+    }
+}
+
+// H:MM:SS or M:SS as exported, or anything parse_length takes
+fn parse_record_length(text: &str) -> Option<Duration> {
+    let text = text.trim();
+
+    if !text.contains(':') {
+        return parse_length(text);
+    }
+
+    let mut seconds: u64 = 0;
+
+    for part in text.split(':') {
+        seconds = seconds
+            .checked_mul(60)?
+            .checked_add(part.parse::<u64>().ok()?)?;
+    }
+
+    Some(Duration::from_secs(seconds))
+}
+
+fn record_from_snapshot(timer: &TimerSnapshot) -> TimerRecord {
+    TimerRecord {
+        description: timer.parts.description.clone(),
+        length: format_duration(timer.parts.length),
+        remaining: Some(format_duration(timer.parts.time_left)),
+        state: Some(String::from(timer.state.name())),
+    }
+}
+
+fn parts_from_record(record: TimerRecord) -> Result<SerializeableTimerParts, String> {
+    if record.description.trim().is_empty() {
+        return Err(String::from("the description is empty"));
+    }
+
+    let length = match parse_record_length(&record.length) {
+        Some(length) if !length.is_zero() => length,
+        _ => return Err(format!("'{}' is not a valid length", record.length)),
+    };
+
+    let time_left = match record.remaining.as_deref().map(str::trim) {
+        None | Some("") => length,
+        Some(remaining) => match parse_record_length(remaining) {
+            Some(time_left) if time_left <= length => time_left,
+            Some(_) => return Err(format!("{} remaining is longer than the length", remaining)),
+            None => return Err(format!("'{}' is not a valid time remaining", remaining)),
+        },
+    };
+
+    // Imported timers start out stopped, like saved ones, but a typo in the
+    // state is still worth pointing out
+    if let Some(state) = record.state.as_deref().map(str::trim) {
+        match state.to_lowercase().as_str() {
+            "" | "stopped" | "running" | "alarming" => (),
+            _ => return Err(format!("'{}' is not a timer state", state)),
+        }
+    }
+
+    Ok(SerializeableTimerParts {
+        length,
+        time_left,
+        description: record.description,
+        webhooks: false,
+        uid: new_uid(),
+    })
+}
+
+pub fn export_timers(timers: &[TimerSnapshot], format: ExchangeFormat) -> Result<String, String> {
+    let records: Vec<TimerRecord> = timers.iter().map(record_from_snapshot).collect();
+
+    match format {
+        ExchangeFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(Vec::new());
+
+            for record in &records {
+                writer
+                    .serialize(record)
+                    .map_err(|error| error.to_string())?;
+            }
+
+            let bytes = writer.into_inner().map_err(|error| error.to_string())?;
+
+            String::from_utf8(bytes).map_err(|error| error.to_string())
+        }
+        ExchangeFormat::Toml => {
+            toml::to_string(&TimerRecords { timers: records }).map_err(|error| error.to_string())
+        }
+        ExchangeFormat::Yaml => serde_yaml::to_string(&TimerRecords { timers: records })
+            .map_err(|error| error.to_string()),
+    }
+}
+
+fn line_of(contents: &str, offset: usize) -> usize {
+    contents[..offset.min(contents.len())].matches('\n').count() + 1
+}
+
+// serde_yaml has no spans, but its errors carry positions. To find the line
+// timers[index] starts on, the file is parsed again and that item refused
+struct YamlItemProbe(usize);
+
+struct YamlItemsProbe(usize);
+
+struct YamlItemRefusal;
+
+impl<'de> Visitor<'de> for YamlItemProbe {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "a timers list")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        while let Some(key) = map.next_key::<String>()? {
+            if key == "timers" {
+                return map.next_value_seed(YamlItemsProbe(self.0));
+            }
+
+            map.next_value::<IgnoredAny>()?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for YamlItemsProbe {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for YamlItemsProbe {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "a list of timers")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        for _ in 0..self.0 {
+            seq.next_element::<IgnoredAny>()?;
+        }
+
+        seq.next_element_seed(YamlItemRefusal).map(|_| ())
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for YamlItemRefusal {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+// Every visit_* left to its default fails, at the position of the item
+impl<'de> Visitor<'de> for YamlItemRefusal {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "nothing")
+    }
+}
+
+fn yaml_item_line(contents: &str, index: usize) -> usize {
+    serde_yaml::Deserializer::from_str(contents)
+        .deserialize_map(YamlItemProbe(index))
+        .err()
+        .and_then(|error| error.location())
+        .map_or(0, |location| location.line())
+}
+
+// Rows come with the line they start on, except YAML ones, whose lines are
+// only looked up for the rows that turn out to be wrong
+fn read_records(
+    contents: &str,
+    format: ExchangeFormat,
+) -> Result<Vec<(Option<usize>, TimerRecord)>, ImportError> {
+    match format {
+        ExchangeFormat::Csv => {
+            let mut reader = csv::Reader::from_reader(contents.as_bytes());
+            let headers = reader
+                .headers()
+                .map_err(|error| ImportError::Parse(error.to_string()))?
+                .clone();
+
+            let mut records = Vec::new();
+            let mut errors = Vec::new();
+
+            for result in reader.records() {
+                let line = match &result {
+                    Ok(record) => record.position(),
+                    Err(error) => error.position(),
+                }
+                .map_or(0, |position| position.line() as usize);
+
+                let record = result.and_then(|record| record.deserialize(Some(&headers)));
+
+                match record {
+                    Ok(record) => records.push((Some(line), record)),
+                    Err(error) => errors.push(RowError {
+                        line,
+                        message: match error.kind() {
+                            csv::ErrorKind::Deserialize { err, .. } => err.to_string(),
+                            _ => error.to_string(),
+                        },
+                    }),
+                }
+            }
+
+            if errors.is_empty() {
+                Ok(records)
+            } else {
+                Err(ImportError::Rows(errors))
+            }
+        }
+        ExchangeFormat::Toml => {
+            let timers: TomlTimers =
+                toml::from_str(contents).map_err(|error| ImportError::Parse(error.to_string()))?;
+
+            Ok(timers
+                .timers
+                .into_iter()
+                .map(|record| {
+                    (
+                        Some(line_of(contents, record.span().start)),
+                        record.into_inner(),
+                    )
+                })
+                .collect())
+        }
+        ExchangeFormat::Yaml => {
+            let timers: YamlTimers =
+                serde_yaml::from_str(contents).map_err(|error| match error.location() {
+                    Some(location) => {
+                        ImportError::Parse(format!("line {}: {}", location.line(), error))
+                    }
+                    None => ImportError::Parse(error.to_string()),
+                })?;
+
+            Ok(timers
+                .timers
+                .into_iter()
+                .map(|record| (None, record))
+                .collect())
+        }
+    }
+}
+
+// Reads timers in any of the formats, checking every row before accepting
+// any of them
+pub fn import_timers(
+    contents: &str,
+    format: ExchangeFormat,
+) -> Result<Vec<SerializeableTimerParts>, ImportError> {
+    let mut timers = Vec::new();
+    let mut errors = Vec::new();
+
+    for (index, (line, record)) in read_records(contents, format)?.into_iter().enumerate() {
+        match parts_from_record(record) {
+            Ok(parts) => timers.push(parts),
+            Err(message) => errors.push(RowError {
+                line: line.unwrap_or_else(|| yaml_item_line(contents, index)),
+                message,
+            }),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(timers)
+    } else {
+        Err(ImportError::Rows(errors))
+    }
+}
+
+pub fn combine_timers(
+    mut existing: Vec<SerializeableTimerParts>,
+    imported: Vec<SerializeableTimerParts>,
+    mode: ImportMode,
+) -> Vec<SerializeableTimerParts> {
+    if mode == ImportMode::Replace {
+        return imported;
+    }
+
+    for timer in imported {
+        match existing
+            .iter_mut()
+            .find(|existing| existing.description == timer.description)
+        {
+            Some(existing) => {
+                existing.length = timer.length;
+                existing.time_left = timer.time_left;
+            }
+            None => existing.push(timer),
+        }
+    }
+
+    existing
+}
+
+fn read_saved_timers(save_file: &Path) -> Result<Vec<SerializeableTimerParts>, String> {
+    match fs::read_to_string(save_file) {
+        Ok(contents) => parse_saved_timers(&contents).map_err(|error| {
+            format!(
+                "Could not load the saved timers in {}: {}",
+                save_file.display(),
+                error
+            )
+        }),
+        Err(error) if error.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+        Err(error) => Err(format!("Could not read {}: {}", save_file.display(), error)),
+    }
+}
+
+// The export subcommand, writing the saved timers to a file or stdout
+pub fn run_export(
+    save_file: &Path,
+    format: Option<ExchangeFormat>,
+    output: &Option<PathBuf>,
+) -> Result<(), String> {
+    let format = format
+        .or_else(|| output.as_deref().and_then(ExchangeFormat::from_path))
+        .unwrap_or(ExchangeFormat::Csv);
+
+    // Saved timers are always stopped
+    let timers: Vec<TimerSnapshot> = read_saved_timers(save_file)?
+        .into_iter()
+        .enumerate()
+        .map(|(id, parts)| TimerSnapshot {
+            id: id as u64,
+            state: TimerState::Stopped,
+            parts,
+        })
+        .collect();
+
+    let exported = export_timers(&timers, format)?;
+
+    match output {
+        Some(output) => fs::write(output, exported)
+            .map_err(|error| format!("Could not write {}: {}", output.display(), error)),
+        None => io::stdout()
+            .write_all(exported.as_bytes())
+            .map_err(|error| error.to_string()),
+    }
+}
+
+// The import subcommand. A running instance picks the change up from the
+// save file
+pub fn run_import(
+    save_file: &Path,
+    input: &Path,
+    format: Option<ExchangeFormat>,
+    mode: ImportMode,
+    backups: u32,
+) -> Result<usize, String> {
+    let format = format
+        .or_else(|| ExchangeFormat::from_path(input))
+        .ok_or_else(|| {
+            format!(
+                "Could not tell the format of {}, pass --format",
+                input.display()
+            )
+        })?;
+
+    let contents = fs::read_to_string(input)
+        .map_err(|error| format!("Could not read {}: {}", input.display(), error))?;
+
+    let imported = import_timers(&contents, format)
+        .map_err(|error| format!("Could not import {}:\n{}", input.display(), error))?;
+    let count = imported.len();
+
+    let timers = combine_timers(read_saved_timers(save_file)?, imported, mode);
+
+    save_timers_file(save_file, &saved_timers_json(&timers), backups)
+        .map_err(|error| format!("Could not save {}: {}", save_file.display(), error))?;
+
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row_errors(contents: &str, format: ExchangeFormat) -> Vec<(usize, String)> {
+        match import_timers(contents, format) {
+            Err(ImportError::Rows(errors)) => errors
+                .into_iter()
+                .map(|error| (error.line, error.message))
+                .collect(),
+            Err(ImportError::Parse(message)) => panic!("the file did not parse: {}", message),
+            Ok(_) => panic!("the import succeeded"),
+        }
+    }
+
+    #[test]
+    fn csv_rows_are_reported_by_line() {
+        let contents = "description,length,remaining,state\n\
+                        Tea,0:03:00,0:01:00,stopped\n\
+                        Eggs,soon,,\n\
+                        Pasta,10m,,running\n";
+
+        assert_eq!(
+            row_errors(contents, ExchangeFormat::Csv),
+            vec![(3, String::from("'soon' is not a valid length"))]
+        );
+    }
+
+    #[test]
+    fn toml_rows_are_reported_by_line() {
+        let contents = "[[timers]]\n\
+                        description = \"Tea\"\n\
+                        length = \"3m\"\n\
+                        \n\
+                        [[timers]]\n\
+                        description = \"Eggs\"\n\
+                        length = \"5m\"\n\
+                        state = \"boiling\"\n";
+
+        assert_eq!(
+            row_errors(contents, ExchangeFormat::Toml),
+            vec![(5, String::from("'boiling' is not a timer state"))]
+        );
+    }
+
+    #[test]
+    fn yaml_rows_are_reported_by_line() {
+        let contents = "timers:\n\
+                        - description: Tea\n  \
+                          length: 3m\n  \
+                          notes:\n  \
+                          - steep\n  \
+                          - pour\n\
+                        - {description: Toast, length: 2m}\n\
+                        - description: Eggs\n  \
+                          length: 5m\n  \
+                          remaining: 6m\n";
+
+        assert_eq!(
+            row_errors(contents, ExchangeFormat::Yaml),
+            vec![(8, String::from("6m remaining is longer than the length"))]
+        );
+    }
+
+    #[test]
+    fn yaml_flow_lists_are_reported_by_line() {
+        let contents =
+            "timers: [\n  {description: Tea, length: 3m},\n  {description: '', length: 2m}\n]\n";
+
+        assert_eq!(
+            row_errors(contents, ExchangeFormat::Yaml),
+            vec![(3, String::from("the description is empty"))]
+        );
+    }
+
+    #[test]
+    fn lengths_too_long_to_count_are_invalid() {
+        let contents = "description,length\nTea,9999999999999999:0:0\nEggs,5124095576030432h\n";
+
+        assert_eq!(
+            row_errors(contents, ExchangeFormat::Csv),
+            vec![
+                (
+                    2,
+                    String::from("'9999999999999999:0:0' is not a valid length")
+                ),
+                (3, String::from("'5124095576030432h' is not a valid length")),
+            ]
+        );
+    }
+}
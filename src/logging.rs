@@ -0,0 +1,232 @@
+use std::{
+    collections::VecDeque,
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::SystemTime,
+};
+
+#[cfg(unix)]
+use std::os::unix::fs::MetadataExt;
+
+use log::{LevelFilter, Log, Metadata, Record};
+use serde::Serialize;
+
+use timers_core::persistence::backup_path;
+
+use crate::{calendar::utc_date, paths::state_dir};
+
+const LOG_FILE_NAME: &str = "timers_tui.log";
+// The log is moved to .1 once it grows past this, and older ones shift up
+const MAX_LOG_SIZE: u64 = 1024 * 1024;
+const KEPT_LOGS: u32 = 3;
+// How many records the log viewer can scroll back through
+const RECENT_RECORDS: usize = 500;
+
+// One line of the log file, as JSON
+#[derive(Serialize, Clone)]
+pub struct LogRecord {
+    pub time: String,
+    pub level: String,
+    pub target: String,
+    pub message: String,
+}
+
+static RECENT: Mutex<VecDeque<LogRecord>> = Mutex::new(VecDeque::new());
+
+struct LogFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+}
+
+impl LogFile {
+    fn open(path: &Path) -> Option<LogFile> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).ok()?;
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .ok()?;
+        let size = file.metadata().map(|meta| meta.len()).unwrap_or(0);
+
+        Some(LogFile {
+            path: path.to_path_buf(),
+            file,
+            size,
+        })
+    }
+
+    fn rotate(&mut self) {
+        for number in (1..KEPT_LOGS).rev() {
+            let _ = fs::rename(
+                backup_path(&self.path, number),
+                backup_path(&self.path, number + 1),
+            );
+        }
+
+        let _ = fs::rename(&self.path, backup_path(&self.path, 1));
+
+        if let Some(log_file) = LogFile::open(&self.path) {
+            *self = log_file;
+        }
+    }
+
+    // Other instances write to the same log and may have rotated it, leaving
+    // this one appending to a backup, so it is reopened if the file at the
+    // path is not the one open. The size counts what they wrote too
+    fn follow_path(&mut self) {
+        match (fs::metadata(&self.path), self.file.metadata()) {
+            (Ok(on_disk), Ok(open)) if same_file(&on_disk, &open) => {
+                self.size = on_disk.len();
+            }
+            _ => {
+                if let Some(log_file) = LogFile::open(&self.path) {
+                    *self = log_file;
+                }
+            }
+        }
+    }
+
+    fn write(&mut self, line: &str) {
+        self.follow_path();
+
+        if self.size >= MAX_LOG_SIZE {
+            self.rotate();
+        }
+
+        if self.file.write_all(line.as_bytes()).is_ok() {
+            self.size += line.len() as u64;
+        }
+    }
+}
+
+#[cfg(unix)]
+fn same_file(on_disk: &fs::Metadata, open: &fs::Metadata) -> bool {
+    on_disk.dev() == open.dev() && on_disk.ino() == open.ino()
+}
+
+// Without inodes, the file at the path is taken to be the open one if it
+// agrees on its length and when it was made
+#[cfg(not(unix))]
+fn same_file(on_disk: &fs::Metadata, open: &fs::Metadata) -> bool {
+    on_disk.len() == open.len() && on_disk.created().ok() == open.created().ok()
+}
+
+// Writes records to the log file and keeps the latest for the log viewer
+struct FileLogger {
+    level: LevelFilter,
+    file: Mutex<Option<LogFile>>,
+}
+
+fn format_time(time: SystemTime) -> String {
+    let (year, month, day, time_of_day) = utc_date(time);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        time_of_day / 3600,
+        time_of_day / 60 % 60,
+        time_of_day % 60
+    )
+}
+
+impl Log for FileLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        // Libraries other than timers_core only log below warnings at -vv
+        let level = match self.level {
+            LevelFilter::Trace => LevelFilter::Trace,
+            _ if metadata.target().starts_with("timers_") => self.level,
+            level => level.min(LevelFilter::Warn),
+        };
+
+        metadata.level() <= level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let record = LogRecord {
+            time: format_time(SystemTime::now()),
+            level: record.level().to_string(),
+            target: String::from(record.target()),
+            message: record.args().to_string(),
+        };
+
+        if let Ok(mut file) = self.file.lock() {
+            if let (Some(file), Ok(json)) = (file.as_mut(), serde_json::to_string(&record)) {
+                file.write(&format!("{}\n", json));
+            }
+        }
+
+        if let Ok(mut recent) = RECENT.lock() {
+            if recent.len() == RECENT_RECORDS {
+                recent.pop_front();
+            }
+
+            recent.push_back(record);
+        }
+    }
+
+    fn flush(&self) {
+        if let Ok(mut file) = self.file.lock() {
+            if let Some(file) = file.as_mut() {
+                let _ = file.file.flush();
+            }
+        }
+    }
+}
+
+pub fn default_log_file() -> Option<PathBuf> {
+    Some(state_dir()?.join(LOG_FILE_NAME))
+}
+
+// Info by default, each -v shows more and each -q shows less
+pub fn log_level(verbose: u8, quiet: u8) -> LevelFilter {
+    const LEVELS: [LevelFilter; 6] = [
+        LevelFilter::Off,
+        LevelFilter::Error,
+        LevelFilter::Warn,
+        LevelFilter::Info,
+        LevelFilter::Debug,
+        LevelFilter::Trace,
+    ];
+
+    let level = (3 + verbose as usize).saturating_sub(quiet as usize);
+
+    LEVELS[level.min(LEVELS.len() - 1)]
+}
+
+// Starts logging to the file. Records are still kept for the log viewer if
+// the file can't be opened. Returns whether the file could be opened
+pub fn init_logging(log_file: &Option<PathBuf>, level: LevelFilter) -> bool {
+    let file = log_file.as_deref().and_then(LogFile::open);
+    let opened = file.is_some();
+
+    let logger = FileLogger {
+        level,
+        file: Mutex::new(file),
+    };
+
+    if log::set_boxed_logger(Box::new(logger)).is_ok() {
+        log::set_max_level(level);
+    }
+
+    opened
+}
+
+// The latest records, oldest first
+pub fn recent_records() -> Vec<LogRecord> {
+    match RECENT.lock() {
+        Ok(recent) => recent.iter().cloned().collect(),
+        Err(_) => Vec::new(),
+    }
+}
@@ -1,26 +1,67 @@
 use std::{
     error::Error,
-    fs::{create_dir_all, File},
-    io::{self, BufReader, Read, Write},
-    path::PathBuf,
-    thread::sleep,
+    fs::File,
+    io::{self, BufReader, ErrorKind, Read},
+    path::{Path, PathBuf},
+    process,
+    sync::{Arc, Mutex},
     time::Duration,
 };
 
-use app::App;
-use clap::Parser;
-use config::Config;
+use app::{App, AppScreen, ErrorType};
+use calendar::CalendarExporter;
+use clap::{ArgAction, Args, Parser, Subcommand};
+use crash::{install_panic_hook, set_emergency_save_file};
 use crossterm::{
     event::{DisableMouseCapture, EnableMouseCapture},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
-use dirs::config_dir;
+use import_export::{run_export, run_import, ExchangeFormat, ImportMode};
+use log::{error, info, warn};
+use logging::{default_log_file, init_logging, log_level};
+use metrics::MetricsExporter;
+#[cfg(feature = "dbus")]
+use notifications::DesktopNotifier;
+use paths::{ensure_state_dir, migrate_legacy_save_file, resolved_paths};
+use presets::load_presets;
+use profiles::{is_valid_profile_name, profile_save_file, DEFAULT_PROFILE};
 use ratatui::{prelude::CrosstermBackend, Terminal};
+use serde_json::Value;
+use server::HttpServer;
+use settings::{
+    check_settings, default_config_file, load_settings, parse_override, settings_files,
+    show_settings, write_default_config, Settings, SettingsSources, SettingsWatcher,
+};
+use shared_save::{ask_save_mode, SaveFileLock, SaveFileWatcher, SaveMode};
+use signals::listen_for_signals;
+use status::{print_status, LiveStateWriter, StatusFormat};
+use terminal_alerts::TerminalAlerts;
+use timers_core::persistence::{backup_path, keep_aside, save_timers_file, Autosave, LoadError};
 use ui::run_app;
+use webhooks::Webhooks;
 
 mod app;
+mod calendar;
+mod crash;
+mod hooks;
+mod import_export;
+mod logging;
+mod metrics;
+#[cfg(feature = "dbus")]
+mod notifications;
+mod paths;
+mod presets;
+mod profiles;
+mod server;
+mod settings;
+mod shared_save;
+mod signals;
+mod sound;
+mod status;
+mod terminal_alerts;
 mod ui;
+mod webhooks;
 
 // const DEFAULT_CONFIG_FILE: &str = "/home/jeanpierre/.config/timers_tui/config.toml";
 // const DEFAULT_SAVE_FILE: &str = "/home/jeanpierre/.config/timers_tui/saved_timers.json";
@@ -33,26 +74,133 @@ struct CliOpts {
 
     #[arg(short, long, value_name = "FILE")]
     save_file: Option<PathBuf>,
+
+    /// Change a setting for this run, like snooze_length=10m or
+    /// hooks.alarm="..."; goes on top of the config files and environment
+    #[arg(long = "set", value_name = "KEY=VALUE", value_parser = parse_override)]
+    overrides: Vec<(String, Value)>,
+
+    /// Use a named profile, with its own saved timers and settings
+    #[arg(short, long, value_name = "NAME")]
+    profile: Option<String>,
+
+    /// If another instance is using the save file, follow it without saving
+    #[arg(long, conflicts_with = "attach")]
+    read_only: bool,
+
+    /// If another instance is using the save file, save alongside it and
+    /// merge its changes
+    #[arg(long)]
+    attach: bool,
+
+    /// Log more, -vv for everything
+    #[arg(short, long, action = ArgAction::Count)]
+    verbose: u8,
+
+    /// Log less, -qq for only errors and -qqq for nothing
+    #[arg(short, long, action = ArgAction::Count, conflicts_with = "verbose")]
+    quiet: u8,
+
+    /// Log here instead of timers_tui.log in the state directory
+    #[arg(long, value_name = "FILE")]
+    log_file: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Option<CliCommand>,
+}
+
+#[derive(Subcommand)]
+enum CliCommand {
+    /// Print the soonest running timer and any alarming timers for a status bar
+    Status(StatusArgs),
+    /// Write the saved timers out as CSV, TOML or YAML
+    Export(ExportArgs),
+    /// Read timers from a CSV, TOML or YAML file into the saved timers
+    Import(ImportArgs),
+    /// Check the config file or write a default one
+    #[command(subcommand)]
+    Config(ConfigCommand),
+    /// Print where every file this would use is
+    Paths,
+}
+
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Report unknown keys and bad values in the config files
+    Check,
+    /// Print every setting in effect and where it came from
+    Show,
+    /// Write a config file with every setting commented out at its default
+    Init {
+        /// Replace a config file that is already there
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+#[derive(Args)]
+struct ExportArgs {
+    /// Defaults to the output file's extension, or CSV
+    #[arg(short, long, value_enum)]
+    format: Option<ExchangeFormat>,
+
+    /// Where to write, stdout if not given
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct ImportArgs {
+    #[arg(value_name = "FILE")]
+    input: PathBuf,
+
+    /// Defaults to the file's extension
+    #[arg(short, long, value_enum)]
+    format: Option<ExchangeFormat>,
+
+    #[arg(short, long, value_enum, default_value = "merge")]
+    mode: ImportMode,
+}
+
+#[derive(Args)]
+struct StatusArgs {
+    #[arg(short, long, value_enum, default_value = "plain")]
+    format: StatusFormat,
+
+    /// Keep printing a new status every interval
+    #[arg(short, long)]
+    watch: bool,
+
+    /// Seconds between updates with --watch
+    #[arg(short, long, default_value_t = 1)]
+    interval: u64,
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
     let args = CliOpts::parse();
 
+    let log_file = args.log_file.clone().or_else(default_log_file);
+
+    if !init_logging(&log_file, log_level(args.verbose, args.quiet)) {
+        if let Some(log_file) = &log_file {
+            eprintln!("Could not open the log file {}", log_file.display());
+        }
+    }
+
     // let config_filename = match args.config {
     //     Some(filename) => filename,
     //     None => PathBuf::from(DEFAULT_CONFIG_FILE),
     // };
 
-    let config_filename = match args.config {
-        Some(filename) => Some(filename),
-        None => match config_dir() {
-            Some(mut config_path) => {
-                config_path.push("timers_tui");
-                config_path.push("config.toml");
-                Some(config_path)
-            }
-            None => None,
+    let settings_sources = SettingsSources {
+        // A config file given on the command line has to be there
+        config_file_required: args.config.is_some(),
+        config_file: match args.config {
+            Some(filename) => Some(filename),
+            None => default_config_file(),
         },
+        save_file: args.save_file.clone(),
+        overrides: args.overrides.clone(),
     };
 
     // let default_map_generator = || -> HashMap<String, String> {
@@ -64,58 +212,177 @@ fn main() -> Result<(), Box<dyn Error>> {
     //
     //     map
     // };
-    let config_options = {
-        let settings = Config::builder();
-
-        // let settings = match config_dir() {
-        //     Some(filename) => {
-        //         let mut save_filename = filename;
-        //         save_filename.push("timers_tui");
-        //         save_filename.push("saved_timers.json");
-        //         // println!("{}", save_filename.to_str().unwrap());
-        //         settings.set_default("save_file", save_filename.to_str().unwrap())?
-        //     }
-        //     None => settings,
-        // };
-
-        match config_filename {
-            Some(filename) => {
-                let settings = settings.add_source(config::File::from(filename));
-                match settings.build() {
-                    Ok(options) => Some(options),
-                    Err(_) => None,
-                }
-            }
-            None => match settings.build() {
-                Ok(options) => Some(options),
-                Err(_) => None,
-            },
+    let mut profile = args
+        .profile
+        .clone()
+        .unwrap_or_else(|| String::from(DEFAULT_PROFILE));
+
+    if !is_valid_profile_name(&profile) {
+        return Err(format!("'{}' is not a valid profile name", profile).into());
+    }
+
+    if let Some(CliCommand::Config(config_command)) = &args.command {
+        run_config_command(config_command, &settings_sources, &profile);
+
+        return Ok(());
+    }
+
+    let settings = match load_settings(&settings_sources, &profile) {
+        Ok(settings) => settings,
+        Err(error) => {
+            eprintln!("{}", error);
+            eprintln!("Run `timers_tui config check` after fixing it to make sure");
+            process::exit(1);
         }
     };
 
     // println!("{:?}", config_options);
 
-    let mut app = App::new();
-
     let timers_filename = match args.save_file {
         Some(filename) => Some(filename),
-        None => match config_options {
-            Some(options) => match options.get(&String::from("save_file")) {
-                Ok(filename) => Some(filename),
-                Err(_) => None,
-            },
-            None => match config_dir() {
-                Some(mut filename) => {
-                    filename.push("timers_tui");
-                    filename.push("saved_timers.json");
-                    Some(filename)
-                }
-                None => None,
-            },
-        },
+        None => default_save_file(&settings, &profile),
     };
 
-    load_timers(&mut app, &timers_filename);
+    if let Some(moved_from) = migrate_save_file(&profile, &timers_filename) {
+        eprintln!(
+            "Moved the saved timers from {} to the data directory",
+            moved_from.display()
+        );
+    }
+
+    match &args.command {
+        Some(CliCommand::Status(status_args)) => {
+            print_status(
+                &profile,
+                &timers_filename,
+                status_args.format,
+                status_args.watch,
+                Duration::from_secs(status_args.interval),
+            )?;
+
+            return Ok(());
+        }
+        Some(CliCommand::Export(export_args)) => {
+            let Some(save_file) = &timers_filename else {
+                return Err("No save file to export timers from".into());
+            };
+
+            if let Err(error) = run_export(save_file, export_args.format, &export_args.output) {
+                eprintln!("{}", error);
+                process::exit(1);
+            }
+
+            return Ok(());
+        }
+        Some(CliCommand::Import(import_args)) => {
+            let Some(save_file) = &timers_filename else {
+                return Err("No save file to import timers into".into());
+            };
+
+            match run_import(
+                save_file,
+                &import_args.input,
+                import_args.format,
+                import_args.mode,
+                settings.backups,
+            ) {
+                Ok(count) => println!("Imported {} timers into {}", count, save_file.display()),
+                Err(error) => {
+                    eprintln!("{}", error);
+                    process::exit(1);
+                }
+            }
+
+            return Ok(());
+        }
+        Some(CliCommand::Paths) => {
+            let paths = resolved_paths(&settings_sources, &settings, &profile, &timers_filename);
+
+            let width = paths.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+
+            for (name, path) in paths {
+                match path {
+                    Some(path) => println!("{:width$}  {}", name, path.display(), width = width),
+                    None => println!("{:width$}  (none)", name, width = width),
+                }
+            }
+
+            return Ok(());
+        }
+        Some(CliCommand::Config(_)) | None => (),
+    }
+
+    let (save_file_lock, save_mode) = lock_save_file(&timers_filename, |save_file| {
+        if args.read_only {
+            SaveMode::ReadOnly
+        } else if args.attach {
+            SaveMode::Attach
+        } else {
+            ask_save_mode(save_file)
+        }
+    });
+
+    let mut app = App::new();
+
+    app.profile = profile.clone();
+
+    // Before raw mode, so a panic anywhere after it gives the terminal back
+    app.add_listener(Box::new(install_panic_hook(timers_filename.clone())));
+
+    let sound_warning = apply_settings(&mut app, &settings);
+
+    if let Err(error) = listen_for_signals(app.command_sender()) {
+        warn!(
+            "Could not listen for signals, a kill will not save timers: {}",
+            error
+        );
+    }
+
+    if let Some(http_port) = settings.http_port {
+        match HttpServer::start(http_port, app.command_sender()) {
+            Ok(server) => app.add_listener(Box::new(server)),
+            Err(error) => error!(
+                "Could not start the HTTP server on port {}: {}",
+                http_port, error
+            ),
+        }
+    }
+
+    if let Some(calendar_file) = &settings.calendar_file {
+        app.add_listener(Box::new(CalendarExporter::new(calendar_file.clone())));
+    }
+
+    if let Some(metrics_port) = settings.metrics_port {
+        match MetricsExporter::start(metrics_port) {
+            Ok(exporter) => app.add_listener(Box::new(exporter)),
+            Err(error) => error!(
+                "Could not start the metrics exporter on port {}: {}",
+                metrics_port, error
+            ),
+        }
+    }
+
+    #[cfg(feature = "dbus")]
+    {
+        if settings.desktop_notifications {
+            match DesktopNotifier::new(app.command_sender()) {
+                Ok(notifier) => app.add_listener(Box::new(notifier)),
+                Err(error) => warn!(
+                    "Could not connect to the session D-Bus, desktop notifications are disabled: {}",
+                    error
+                ),
+            }
+        }
+    }
+
+    let (mut session, load_warning) = open_profile(
+        &mut app,
+        &settings,
+        settings_files(&settings_sources, &profile),
+        timers_filename,
+        save_file_lock,
+        save_mode,
+    );
 
     enable_raw_mode()?;
     let mut stderr = io::stderr();
@@ -125,17 +392,106 @@ fn main() -> Result<(), Box<dyn Error>> {
     let backend = CrosstermBackend::new(stderr);
     let mut terminal = Terminal::new(backend)?;
 
-    match app.successful_init {
-        true => {
-            let _ = run_app(&mut app, &mut terminal);
+    if !app.successful_init {
+        app.screen = AppScreen::Error(ErrorType::SoundDevice);
+    }
+
+    if let Some(sound_warning) = sound_warning {
+        app.screen = AppScreen::Error(sound_warning);
+    }
+
+    if let Some(load_warning) = load_warning {
+        app.screen = AppScreen::Error(load_warning);
+    }
+
+    loop {
+        let _ = run_app(&mut app, &mut terminal);
+
+        // A config file changed: apply it without touching the timers
+        if app.reload_settings {
+            app.reload_settings = false;
+
+            reload_settings(&mut app, &settings_sources, &profile, &mut session);
+
+            continue;
         }
-        false => {
-            println!("WARNING: Could not initialize sound playback. You will not hear any sound when an alarm goes off.");
 
-            sleep(Duration::from_millis(5000));
+        if let Some(next_profile) = app.switch_profile.take() {
+            app.should_reload = false;
+
+            // Its alarms are acknowledged like any other, so listeners hear
+            // about it, then it is saved before the next one is opened
+            app.engine.acknowledge_all();
+            close_profile(&mut app, session);
+            app.remove_all_timers();
+
+            profile = next_profile;
+            app.profile = profile.clone();
+
+            // A profile with a broken config opens with the defaults, and the
+            // error is shown once it is open
+            let (settings, settings_warning) = match load_settings(&settings_sources, &profile) {
+                Ok(settings) => (settings, None),
+                Err(error) => (
+                    Settings::default(),
+                    Some(ErrorType::Settings(error.to_string())),
+                ),
+            };
+            let sound_warning = apply_settings(&mut app, &settings);
+
+            // There is no asking from inside the TUI, so a profile another
+            // instance holds is followed read-only
+            let timers_filename = default_save_file(&settings, &profile);
+
+            if let Some(moved_from) = migrate_save_file(&profile, &timers_filename) {
+                app.show_toast(format!(
+                    "Moved the saved timers from {}",
+                    moved_from.display()
+                ));
+            }
+
+            let (save_file_lock, save_mode) =
+                lock_save_file(&timers_filename, |_| SaveMode::ReadOnly);
+
+            let load_warning;
+            (session, load_warning) = open_profile(
+                &mut app,
+                &settings,
+                settings_files(&settings_sources, &profile),
+                timers_filename,
+                save_file_lock,
+                save_mode,
+            );
+
+            if save_mode == SaveMode::ReadOnly {
+                app.screen = AppScreen::Error(ErrorType::ProfileInUse(profile.clone()));
+            }
+
+            if let Some(load_warning) = load_warning {
+                app.screen = AppScreen::Error(load_warning);
+            }
+
+            if let Some(warning) = sound_warning.or(settings_warning) {
+                app.screen = AppScreen::Error(warning);
+            }
 
-            let _ = run_app(&mut app, &mut terminal);
+            terminal.clear()?;
+            continue;
         }
+
+        if !app.should_reload {
+            break;
+        }
+
+        // SIGHUP: apply the config again and take in what changed in the
+        // save file, the same way as when either changes on its own. Timers
+        // keep running and nothing unsaved is lost
+        app.should_reload = false;
+
+        reload_settings(&mut app, &settings_sources, &profile, &mut session);
+        merge_save_file(&mut app, &session);
+
+        terminal.clear()?;
     }
 
     // run_app(&mut app, &mut terminal)?;
@@ -152,68 +508,391 @@ fn main() -> Result<(), Box<dyn Error>> {
     )?;
     terminal.show_cursor()?;
 
-    save_timers(&app, &timers_filename);
+    close_profile(&mut app, session);
 
     // println!("{}", app.dump_json());
 
     Ok(())
 }
 
-fn load_timers(app: &mut App, input_filename: &Option<PathBuf>) {
-    // let input_filename = String::from("saved_timers.json");
+// `config check`, `config show` and `config init`, which exit with an error
+// code on failure
+fn run_config_command(config_command: &ConfigCommand, sources: &SettingsSources, profile: &str) {
+    let Some(config_file) = &sources.config_file else {
+        eprintln!("Could not find where the config file goes");
+        process::exit(1);
+    };
 
-    match input_filename {
-        Some(input_filename) => {
-            let input_file = File::open(input_filename);
+    match config_command {
+        ConfigCommand::Check => match check_settings(sources, profile) {
+            Ok(checked) if checked.is_empty() => {
+                println!(
+                    "No config file at {}, using the defaults",
+                    config_file.display()
+                )
+            }
+            Ok(checked) => {
+                for source in checked {
+                    println!("{}: ok", source);
+                }
+            }
+            Err(error) => {
+                eprintln!("{}", error);
+                process::exit(1);
+            }
+        },
+        ConfigCommand::Show => match show_settings(sources, profile) {
+            Ok(settings) => {
+                let settings: Vec<(String, String)> = settings
+                    .into_iter()
+                    .map(|(key, value, source)| {
+                        let value = match value {
+                            Value::Null => String::from("(not set)"),
+                            value => value.to_string(),
+                        };
+
+                        (format!("{} = {}", key, value), source)
+                    })
+                    .collect();
+
+                let width = settings
+                    .iter()
+                    .map(|(setting, _)| setting.chars().count())
+                    .max()
+                    .unwrap_or(0);
+
+                for (setting, source) in settings {
+                    println!("{:width$}  # {}", setting, source, width = width);
+                }
+            }
+            Err(error) => {
+                eprintln!("{}", error);
+                process::exit(1);
+            }
+        },
+        ConfigCommand::Init { force } => {
+            // A profile's own config only holds what it changes, so init
+            // always writes the main config file
+            match write_default_config(config_file, *force) {
+                Ok(()) => println!("Wrote {}", config_file.display()),
+                Err(error) => {
+                    eprintln!("{}", error);
+                    process::exit(1);
+                }
+            }
+        }
+    }
+}
+
+fn default_save_file(settings: &Settings, profile: &str) -> Option<PathBuf> {
+    if profile != DEFAULT_PROFILE {
+        return profile_save_file(profile);
+    }
 
-            match input_file {
-                Ok(file) => {
-                    let mut reader = BufReader::new(file);
+    match &settings.save_file {
+        Some(save_file) => Some(save_file.clone()),
+        None => paths::default_save_file(DEFAULT_PROFILE),
+    }
+}
 
-                    let mut contents = String::new();
+// Saved timers from before they moved to the data directory. Returns where
+// they were moved from
+fn migrate_save_file(profile: &str, timers_filename: &Option<PathBuf>) -> Option<PathBuf> {
+    let save_file = timers_filename.as_ref()?;
+    let moved_from = migrate_legacy_save_file(profile, save_file)?;
 
-                    reader.read_to_string(&mut contents).unwrap();
+    info!(
+        "Moved the saved timers from {} to {}",
+        moved_from.display(),
+        save_file.display()
+    );
 
-                    match app.read_from_json(&contents) {
-                        Ok(_) => (),
-                        Err(_) => println!("Could not parse the saved timers json file correctly"),
-                    }
-                }
-                Err(_) => println!("Could not open the saved timers json file"),
+    Some(moved_from)
+}
+
+// Locks the save file, or asks when_busy what to do if another instance has
+fn lock_save_file(
+    timers_filename: &Option<PathBuf>,
+    when_busy: impl FnOnce(&Path) -> SaveMode,
+) -> (Option<SaveFileLock>, SaveMode) {
+    match timers_filename {
+        Some(save_file) => match SaveFileLock::acquire(save_file) {
+            Ok(Some(lock)) => (Some(lock), SaveMode::Owner),
+            Ok(None) => (None, when_busy(save_file)),
+            Err(error) => {
+                warn!("Could not lock the saved timers file, other instances will not notice this one: {}", error);
+                (None, SaveMode::Owner)
             }
+        },
+        None => (None, SaveMode::Owner),
+    }
+}
+
+// Everything tied to the save file of the profile in use
+struct ProfileSession {
+    // Where to save, None when this instance must not write to it
+    save_file: Option<PathBuf>,
+    backups: u32,
+    // Another instance owns the save file, so this one never saves
+    read_only: bool,
+    // What the Autosave listener was built from: autosave, the interval and
+    // backups
+    autosave: Option<(bool, Duration, u32)>,
+    // Held while the profile is open, so other instances know it is taken
+    _lock: Option<SaveFileLock>,
+    watcher: Option<Arc<Mutex<SaveFileWatcher>>>,
+    // Watches the profile's config files until the profile is closed
+    _settings_watcher: Arc<Mutex<SettingsWatcher>>,
+}
+
+// Loads a profile's timers and starts saving and watching its save file and
+// config files. Returns a warning to show if the save file could not be loaded
+fn open_profile(
+    app: &mut App,
+    settings: &Settings,
+    settings_files: Vec<PathBuf>,
+    timers_filename: Option<PathBuf>,
+    lock: Option<SaveFileLock>,
+    save_mode: SaveMode,
+) -> (ProfileSession, Option<ErrorType>) {
+    let backups = settings.backups;
+
+    let read_only = save_mode == SaveMode::ReadOnly;
+
+    info!(
+        "Opening the {} profile{}",
+        app.profile,
+        if read_only { " read-only" } else { "" }
+    );
+
+    let load_warning = load_timers(app, &timers_filename, backups, read_only);
+
+    // Never write over a save file that could not be loaded and is still there
+    let save_file = match &load_warning {
+        _ if read_only => None,
+        Some(ErrorType::BrokenSaveFile {
+            kept_aside: None, ..
+        }) => None,
+        _ => timers_filename.clone(),
+    };
+
+    let watcher = timers_filename
+        .as_ref()
+        .map(|path| SaveFileWatcher::spawn(path.clone(), app.command_sender()));
+
+    set_emergency_save_file(timers_filename.clone());
+
+    // Read-only instances leave the live state to the one that owns the
+    // profile
+    if !read_only {
+        if let Some(live_state_writer) = LiveStateWriter::new(&app.profile, timers_filename) {
+            app.add_profile_listener(Box::new(live_state_writer));
         }
-        None => println!("Could not find a command line argument, configuration option, or default value specifying which file to load timers from"), 
     }
+
+    let mut session = ProfileSession {
+        save_file,
+        backups,
+        read_only,
+        autosave: None,
+        _lock: lock,
+        watcher,
+        _settings_watcher: SettingsWatcher::spawn(settings_files, app.command_sender()),
+    };
+
+    start_autosave(app, settings, &mut session);
+
+    (session, load_warning)
 }
 
-fn write_timers_file(app: &App, output_filename: &PathBuf) {
-    let output_file = File::create(output_filename);
+// Starts saving the timers as the settings say, and again whenever a reload
+// changes autosave, autosave_interval or backups
+fn start_autosave(app: &mut App, settings: &Settings, session: &mut ProfileSession) {
+    let autosave = (
+        settings.autosave,
+        settings.autosave_interval.0,
+        settings.backups,
+    );
+
+    if session.autosave == Some(autosave) {
+        return;
+    }
 
-    match output_file {
-        Ok(mut file) => match file.write_all(app.dump_json().as_bytes()) {
-            Ok(_) => (),
-            Err(_) => eprintln!("Could not save timers to json file"),
+    session.autosave = Some(autosave);
+    session.backups = settings.backups;
+
+    let autosave = match (settings.autosave, &session.save_file) {
+        (true, Some(save_file)) => {
+            // Zero only saves on changes
+            let autosave_interval = Some(settings.autosave_interval.0).filter(|i| !i.is_zero());
+
+            Some(Autosave::new(
+                save_file.clone(),
+                settings.backups,
+                autosave_interval,
+            ))
+        }
+        _ => None,
+    };
+
+    app.set_autosave(autosave);
+}
+
+// Saves the profile's timers and lets go of its save file
+fn close_profile(app: &mut App, session: ProfileSession) {
+    // Take in anything another instance saved since the last check, so
+    // saving doesn't drop it
+    app.process_commands();
+    merge_save_file(app, &session);
+
+    if !session.read_only {
+        save_timers(app, &session.save_file, session.backups);
+    }
+
+    app.set_autosave(None);
+    app.clear_profile_listeners();
+}
+
+// Applies what changed in the save file since it was last seen, without
+// waiting for the watcher's next look
+fn merge_save_file(app: &mut App, session: &ProfileSession) {
+    if let Some(watcher) = &session.watcher {
+        if let Ok(mut watcher) = watcher.lock() {
+            if let Some((before, after)) = watcher.check() {
+                app.merge_external(&before, &after);
+            }
+        }
+    }
+}
+
+// Loads the settings again and applies them, or keeps the settings in use if
+// the config is broken
+fn reload_settings(
+    app: &mut App,
+    sources: &SettingsSources,
+    profile: &str,
+    session: &mut ProfileSession,
+) {
+    match load_settings(sources, profile) {
+        Ok(settings) => {
+            info!("Reloaded the config");
+
+            start_autosave(app, &settings, session);
+
+            match apply_settings(app, &settings) {
+                Some(sound_warning) => app.show_error(sound_warning),
+                None => app.show_toast(String::from("Reloaded the config")),
+            }
+        }
+        Err(error) => {
+            warn!("Kept the settings in use, the config is broken: {}", error);
+            app.show_error(ErrorType::Settings(error.to_string()))
+        }
+    }
+}
+
+// Settings that can change while running, applied at startup, when a config
+// file changes and when SIGHUP asks for a reload. Returns a warning to show
+// if the alarm sound could not be played
+fn apply_settings(app: &mut App, settings: &Settings) -> Option<ErrorType> {
+    app.presets = load_presets(settings);
+    app.engine.snooze_length = settings.snooze_length.0;
+
+    let sound_warning = app.set_alarm_sound(&settings.alarm_sound);
+
+    app.clear_settings_listeners();
+
+    if !settings.hooks.is_empty() {
+        app.add_settings_listener(Box::new(settings.hooks.clone()));
+    }
+
+    // Without sound the bell keeps ringing while a timer alarms
+    app.add_settings_listener(Box::new(TerminalAlerts::new(
+        settings.terminal_notifications,
+        settings.window_title,
+        !app.successful_init,
+    )));
+
+    if let Some(webhooks) = Webhooks::new(&settings.webhooks) {
+        app.add_settings_listener(Box::new(webhooks));
+    }
+
+    sound_warning
+}
+
+fn read_timers_file(app: &mut App, input_filename: &Path) -> Result<(), LoadError> {
+    let mut reader = BufReader::new(File::open(input_filename)?);
+
+    let mut contents = String::new();
+
+    reader.read_to_string(&mut contents)?;
+
+    app.read_from_json(&contents)
+}
+
+// Loads the saved timers. A save file that is there but can't be loaded is
+// kept aside, unless read-only, and the newest backup that loads is used
+// instead, which is returned as a warning to show
+fn load_timers(
+    app: &mut App,
+    input_filename: &Option<PathBuf>,
+    backups: u32,
+    read_only: bool,
+) -> Option<ErrorType> {
+    // let input_filename = String::from("saved_timers.json");
+
+    match input_filename {
+        Some(input_filename) => match read_timers_file(app, input_filename) {
+            Ok(_) => None,
+            Err(LoadError::Io(error)) if error.kind() == ErrorKind::NotFound => {
+                info!(
+                    "No saved timers at {}, starting without any",
+                    input_filename.display()
+                );
+                None
+            }
+            Err(error) => {
+                error!(
+                    "Could not load the saved timers from {}: {}",
+                    input_filename.display(),
+                    error
+                );
+
+                let restored = (1..=backups)
+                    .map(|number| backup_path(input_filename, number))
+                    .find(|backup| read_timers_file(app, backup).is_ok());
+
+                Some(ErrorType::BrokenSaveFile {
+                    reason: error.to_string(),
+                    kept_aside: if read_only {
+                        None
+                    } else {
+                        keep_aside(input_filename, ensure_state_dir())
+                    },
+                    restored,
+                })
+            }
         },
-        Err(_) => eprintln!("Could not open json file to save timers to"),
+        None => {
+            warn!("Could not find a command line argument, configuration option, or default value specifying which file to load timers from");
+            None
+        }
     }
 }
 
-fn save_timers(app: &App, output_filename: &Option<PathBuf>) {
+fn save_timers(app: &App, output_filename: &Option<PathBuf>, backups: u32) {
     // let output_filename = String::from("saved_timers.json");
     match output_filename {
         Some(output_filename) => {
-            if !output_filename.exists() {
-                match output_filename.parent() {
-                    Some(filepath) => match create_dir_all(filepath) {
-                        Ok(_) => write_timers_file(app, &output_filename),
-                        Err(_) => eprintln!("Could not create directory to save timers file to"),
-                    },
-                    None => eprintln!("Cannot save timers to '/'"),
-                }
-            } else {
-                write_timers_file(app, &output_filename);
+            match save_timers_file(output_filename, &app.engine.dump_json(), backups) {
+                Ok(()) => info!("Saved the timers to {}", output_filename.display()),
+                Err(error) => error!(
+                    "Could not save the timers to {}: {}",
+                    output_filename.display(),
+                    error
+                ),
             }
         }
-        None => println!("Could not find a command line argument, configuration option, or default value specifying which file to save timers to"),
+        None => warn!("Could not find a command line argument, configuration option, or default value specifying which file to save timers to"),
     }
 }
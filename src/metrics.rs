@@ -0,0 +1,263 @@
+use std::{
+    fmt::Write,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use tiny_http::{Header, Method, Response, Server};
+
+use timers_core::{TimerEvent, TimerEventKind, TimerEventListener, TimerSnapshot, TimerState};
+
+#[derive(Default)]
+struct MetricsState {
+    timers: Vec<TimerSnapshot>,
+    alarms: u64,
+    acknowledgements: u64,
+    snoozes: u64,
+}
+
+// Serves Prometheus text format metrics on localhost at /metrics
+pub struct MetricsExporter {
+    state: Arc<Mutex<MetricsState>>,
+}
+
+impl MetricsExporter {
+    pub fn start(port: u16) -> Result<MetricsExporter, Box<dyn std::error::Error + Send + Sync>> {
+        let server = Server::http(("127.0.0.1", port))?;
+
+        let state = Arc::new(Mutex::new(MetricsState::default()));
+        let server_state = Arc::clone(&state);
+
+        thread::spawn(move || {
+            for request in server.incoming_requests() {
+                let _ = match (request.method(), request.url()) {
+                    (Method::Get, "/metrics") => {
+                        let body = render(&server_state.lock().unwrap());
+
+                        request.respond(
+                            Response::from_string(body).with_header(
+                                Header::from_bytes(
+                                    "Content-Type",
+                                    "text/plain; version=0.0.4; charset=utf-8",
+                                )
+                                .unwrap(),
+                            ),
+                        )
+                    }
+                    _ => request.respond(Response::empty(404)),
+                };
+            }
+        });
+
+        Ok(MetricsExporter { state })
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn render(state: &MetricsState) -> String {
+    let mut text = String::new();
+
+    let labels = |timer: &TimerSnapshot| {
+        format!(
+            "id=\"{}\",description=\"{}\",state=\"{}\"",
+            timer.id,
+            escape_label(&timer.parts.description),
+            timer.state.name()
+        )
+    };
+
+    let _ = writeln!(
+        text,
+        "# HELP timers_tui_timer_remaining_seconds Time left on the timer."
+    );
+    let _ = writeln!(text, "# TYPE timers_tui_timer_remaining_seconds gauge");
+    for timer in &state.timers {
+        let _ = writeln!(
+            text,
+            "timers_tui_timer_remaining_seconds{{{}}} {}",
+            labels(timer),
+            timer.parts.time_left.as_secs_f64()
+        );
+    }
+
+    let _ = writeln!(
+        text,
+        "# HELP timers_tui_timer_length_seconds Length of the timer."
+    );
+    let _ = writeln!(text, "# TYPE timers_tui_timer_length_seconds gauge");
+    for timer in &state.timers {
+        let _ = writeln!(
+            text,
+            "timers_tui_timer_length_seconds{{{}}} {}",
+            labels(timer),
+            timer.parts.length.as_secs_f64()
+        );
+    }
+
+    let alarming = state
+        .timers
+        .iter()
+        .filter(|timer| matches!(timer.state, TimerState::Alarming))
+        .count();
+
+    let _ = writeln!(
+        text,
+        "# HELP timers_tui_alarming_timers Timers alarming right now."
+    );
+    let _ = writeln!(text, "# TYPE timers_tui_alarming_timers gauge");
+    let _ = writeln!(text, "timers_tui_alarming_timers {}", alarming);
+
+    for (name, help, value) in [
+        ("alarms", "Alarms fired.", state.alarms),
+        (
+            "acknowledgements",
+            "Alarms acknowledged.",
+            state.acknowledgements,
+        ),
+        ("snoozes", "Alarms snoozed.", state.snoozes),
+    ] {
+        let _ = writeln!(text, "# HELP timers_tui_{}_total {}", name, help);
+        let _ = writeln!(text, "# TYPE timers_tui_{}_total counter", name);
+        let _ = writeln!(text, "timers_tui_{}_total {}", name, value);
+    }
+
+    text
+}
+
+impl TimerEventListener for MetricsExporter {
+    fn on_event(&mut self, event: &TimerEvent) {
+        let mut state = self.state.lock().unwrap();
+
+        match event.kind {
+            TimerEventKind::Alarmed => state.alarms += 1,
+            TimerEventKind::Acknowledged => state.acknowledgements += 1,
+            TimerEventKind::Snoozed => state.snoozes += 1,
+            _ => (),
+        }
+    }
+
+    fn on_update(&mut self, timers: &[TimerSnapshot]) {
+        self.state.lock().unwrap().timers = timers.to_vec();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::TcpListener, time::Duration};
+
+    use timers_core::SerializeableTimerParts;
+
+    use super::*;
+
+    fn free_port() -> u16 {
+        TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port()
+    }
+
+    fn snapshot(id: u64, description: &str, state: TimerState, time_left: u64) -> TimerSnapshot {
+        TimerSnapshot {
+            id,
+            state,
+            parts: SerializeableTimerParts {
+                length: Duration::from_secs(300),
+                time_left: Duration::from_secs(time_left),
+                description: String::from(description),
+                webhooks: false,
+                uid: id,
+            },
+        }
+    }
+
+    fn event(kind: TimerEventKind) -> TimerEvent {
+        TimerEvent {
+            kind,
+            id: 0,
+            index: 0,
+            description: String::new(),
+            length: Duration::ZERO,
+            time_left: Duration::ZERO,
+            overtime: Duration::ZERO,
+            webhooks: false,
+        }
+    }
+
+    #[test]
+    fn scrape_is_in_the_text_exposition_format() {
+        let port = free_port();
+        let mut exporter = MetricsExporter::start(port).unwrap();
+
+        exporter.on_update(&[
+            snapshot(0, "Tea", TimerState::Running, 120),
+            snapshot(1, "Say \"hi\"\nback\\slash", TimerState::Alarming, 0),
+        ]);
+        exporter.on_event(&event(TimerEventKind::Alarmed));
+        exporter.on_event(&event(TimerEventKind::Alarmed));
+        exporter.on_event(&event(TimerEventKind::Acknowledged));
+
+        let response = ureq::get(&format!("http://127.0.0.1:{}/metrics", port))
+            .call()
+            .unwrap();
+
+        assert_eq!(
+            response.header("Content-Type"),
+            Some("text/plain; version=0.0.4; charset=utf-8")
+        );
+
+        let body = response.into_string().unwrap();
+        let lines: Vec<&str> = body.lines().collect();
+
+        for expected in [
+            r#"timers_tui_timer_remaining_seconds{id="0",description="Tea",state="running"} 120"#,
+            r#"timers_tui_timer_remaining_seconds{id="1",description="Say \"hi\"\nback\\slash",state="alarming"} 0"#,
+            r#"timers_tui_timer_length_seconds{id="0",description="Tea",state="running"} 300"#,
+            "timers_tui_alarming_timers 1",
+            "timers_tui_alarms_total 2",
+            "timers_tui_acknowledgements_total 1",
+            "timers_tui_snoozes_total 0",
+        ] {
+            assert!(lines.contains(&expected), "missing {}", expected);
+        }
+
+        // Every sample belongs to the family declared just before it, with
+        // its HELP and TYPE, and has a number for a value
+        let mut family = None;
+
+        for line in &lines {
+            if let Some(help) = line.strip_prefix("# HELP ") {
+                family = help.split(' ').next();
+            } else if let Some(kind) = line.strip_prefix("# TYPE ") {
+                let (name, kind) = kind.split_once(' ').unwrap();
+
+                assert_eq!(Some(name), family);
+                assert!(kind == "gauge" || kind == "counter");
+            } else {
+                let (name, value) = line.rsplit_once(' ').unwrap();
+                let name = name.split('{').next().unwrap();
+
+                assert_eq!(Some(name), family, "{}", line);
+                assert!(value.parse::<f64>().is_ok(), "{}", line);
+            }
+        }
+
+        assert!(body.ends_with('\n'));
+    }
+
+    #[test]
+    fn only_serves_metrics() {
+        let port = free_port();
+        let _exporter = MetricsExporter::start(port).unwrap();
+
+        let response = ureq::get(&format!("http://127.0.0.1:{}/", port)).call();
+
+        assert!(matches!(response, Err(ureq::Error::Status(404, _))));
+    }
+}
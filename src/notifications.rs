@@ -0,0 +1,309 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        mpsc::{channel, Sender},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+use zbus::{
+    blocking::{Connection, Proxy},
+    zvariant::Value,
+};
+
+use timers_core::{TimerEvent, TimerEventKind, TimerEventListener};
+
+use crate::app::TimerCommand;
+
+const NOTIFICATIONS_NAME: &str = "org.freedesktop.Notifications";
+const NOTIFICATIONS_PATH: &str = "/org/freedesktop/Notifications";
+
+// Shows freedesktop notifications for alarming timers over the session bus,
+// and turns the dismiss and snooze actions back into timer commands. The bus
+// is found through DBUS_SESSION_BUS_ADDRESS, so it can be pointed at a local
+// dbus-daemon
+pub struct DesktopNotifier {
+    event_sender: Sender<TimerEvent>,
+}
+
+impl DesktopNotifier {
+    pub fn new(command_sender: Sender<TimerCommand>) -> zbus::Result<DesktopNotifier> {
+        DesktopNotifier::with_connection(Connection::session()?, command_sender)
+    }
+
+    fn with_connection(
+        connection: Connection,
+        command_sender: Sender<TimerCommand>,
+    ) -> zbus::Result<DesktopNotifier> {
+        let proxy = Proxy::new(
+            &connection,
+            NOTIFICATIONS_NAME,
+            NOTIFICATIONS_PATH,
+            NOTIFICATIONS_NAME,
+        )?;
+
+        // Subscribe before anything is shown so no action can be missed
+        let actions = proxy.receive_signal("ActionInvoked")?;
+
+        // Notification id -> timer id
+        let shown: Arc<Mutex<HashMap<u32, u64>>> = Arc::new(Mutex::new(HashMap::new()));
+
+        let action_shown = Arc::clone(&shown);
+
+        thread::spawn(move || {
+            for message in actions {
+                let Ok((notification_id, action)) = message.body().deserialize::<(u32, String)>()
+                else {
+                    continue;
+                };
+
+                let Some(timer_id) = action_shown.lock().unwrap().remove(&notification_id) else {
+                    continue;
+                };
+
+                let command = match action.as_str() {
+                    "dismiss" => TimerCommand::Acknowledge(timer_id),
+                    "snooze" => TimerCommand::Snooze(timer_id),
+                    _ => continue,
+                };
+
+                if command_sender.send(command).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let (event_sender, event_receiver) = channel::<TimerEvent>();
+
+        // D-Bus calls happen here so a slow notification daemon never stalls
+        // the event loop
+        thread::spawn(move || {
+            for event in event_receiver {
+                match event.kind {
+                    TimerEventKind::Alarmed => {
+                        if let Ok(notification_id) = show_notification(&proxy, &event) {
+                            shown.lock().unwrap().insert(notification_id, event.id);
+                        }
+                    }
+                    TimerEventKind::Acknowledged
+                    | TimerEventKind::Snoozed
+                    | TimerEventKind::Deleted => {
+                        let notification_id = {
+                            let mut shown = shown.lock().unwrap();
+
+                            let notification_id = shown
+                                .iter()
+                                .find(|(_, &timer_id)| timer_id == event.id)
+                                .map(|(&notification_id, _)| notification_id);
+
+                            if let Some(notification_id) = notification_id {
+                                shown.remove(&notification_id);
+                            }
+
+                            notification_id
+                        };
+
+                        if let Some(notification_id) = notification_id {
+                            let _: zbus::Result<()> =
+                                proxy.call("CloseNotification", &(notification_id,));
+                        }
+                    }
+                    _ => (),
+                }
+            }
+        });
+
+        Ok(DesktopNotifier { event_sender })
+    }
+}
+
+fn show_notification(proxy: &Proxy, event: &TimerEvent) -> zbus::Result<u32> {
+    let actions = vec!["dismiss", "Dismiss", "snooze", "Snooze"];
+
+    let mut hints: HashMap<&str, Value> = HashMap::new();
+    // Critical urgency and resident so the notification waits for the user
+    hints.insert("urgency", Value::U8(2));
+    hints.insert("resident", Value::Bool(true));
+
+    proxy.call(
+        "Notify",
+        &(
+            "Timers TUI",
+            0u32,
+            "alarm-symbolic",
+            "Timer finished",
+            event.description.as_str(),
+            actions,
+            hints,
+            0i32,
+        ),
+    )
+}
+
+impl TimerEventListener for DesktopNotifier {
+    fn on_event(&mut self, event: &TimerEvent) {
+        let _ = self.event_sender.send(event.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader},
+        process::{Child, Command, Stdio},
+        sync::mpsc::Receiver,
+        time::{Duration, Instant},
+    };
+
+    use zbus::{blocking::connection, zvariant::OwnedValue};
+
+    use super::*;
+
+    // A bus of our own, so the test never shows anything on the desktop
+    struct SessionBus(Child);
+
+    impl Drop for SessionBus {
+        fn drop(&mut self) {
+            let _ = self.0.kill();
+            let _ = self.0.wait();
+        }
+    }
+
+    fn start_session_bus() -> Option<(SessionBus, String)> {
+        let mut child = Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .ok()?;
+
+        let mut address = String::new();
+        BufReader::new(child.stdout.take()?)
+            .read_line(&mut address)
+            .ok()?;
+
+        Some((SessionBus(child), String::from(address.trim())))
+    }
+
+    #[derive(Default)]
+    struct Calls {
+        shown: Vec<String>,
+        closed: Vec<u32>,
+    }
+
+    // Stands in for the notification daemon, numbering notifications from 1
+    struct FakeNotifications {
+        calls: Arc<Mutex<Calls>>,
+    }
+
+    #[zbus::interface(name = "org.freedesktop.Notifications")]
+    impl FakeNotifications {
+        #[allow(clippy::too_many_arguments)]
+        fn notify(
+            &self,
+            _app_name: &str,
+            _replaces_id: u32,
+            _app_icon: &str,
+            _summary: &str,
+            body: &str,
+            _actions: Vec<String>,
+            _hints: HashMap<String, OwnedValue>,
+            _expire_timeout: i32,
+        ) -> u32 {
+            let mut calls = self.calls.lock().unwrap();
+            calls.shown.push(String::from(body));
+
+            calls.shown.len() as u32
+        }
+
+        fn close_notification(&self, id: u32) {
+            self.calls.lock().unwrap().closed.push(id);
+        }
+    }
+
+    fn event(kind: TimerEventKind, id: u64) -> TimerEvent {
+        TimerEvent {
+            kind,
+            id,
+            index: 0,
+            description: format!("Timer {}", id),
+            length: Duration::from_secs(60),
+            time_left: Duration::ZERO,
+            overtime: Duration::ZERO,
+            webhooks: false,
+        }
+    }
+
+    fn wait_for(calls: &Arc<Mutex<Calls>>, done: impl Fn(&Calls) -> bool) -> bool {
+        let started = Instant::now();
+
+        while started.elapsed() < Duration::from_secs(5) {
+            if done(&calls.lock().unwrap()) {
+                return true;
+            }
+
+            thread::sleep(Duration::from_millis(20));
+        }
+
+        false
+    }
+
+    fn next_command(commands: &Receiver<TimerCommand>) -> Option<TimerCommand> {
+        commands.recv_timeout(Duration::from_secs(5)).ok()
+    }
+
+    #[test]
+    fn shows_closes_and_acts_on_notifications() {
+        let (_bus, address) = start_session_bus().expect("this test needs dbus-daemon");
+
+        let calls = Arc::new(Mutex::new(Calls::default()));
+
+        let daemon = connection::Builder::address(address.as_str())
+            .unwrap()
+            .name(NOTIFICATIONS_NAME)
+            .unwrap()
+            .serve_at(
+                NOTIFICATIONS_PATH,
+                FakeNotifications {
+                    calls: Arc::clone(&calls),
+                },
+            )
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let connection = connection::Builder::address(address.as_str())
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let (command_sender, commands) = channel();
+        let mut notifier = DesktopNotifier::with_connection(connection, command_sender).unwrap();
+
+        notifier.on_event(&event(TimerEventKind::Alarmed, 7));
+        assert!(wait_for(&calls, |calls| calls.shown == ["Timer 7"]));
+
+        // Snoozing from the notification snoozes the timer it was shown for
+        daemon
+            .emit_signal(
+                None::<()>,
+                NOTIFICATIONS_PATH,
+                NOTIFICATIONS_NAME,
+                "ActionInvoked",
+                &(1u32, "snooze"),
+            )
+            .unwrap();
+        assert!(matches!(
+            next_command(&commands),
+            Some(TimerCommand::Snooze(7))
+        ));
+
+        // Acknowledging in the app closes the notification
+        notifier.on_event(&event(TimerEventKind::Alarmed, 8));
+        assert!(wait_for(&calls, |calls| calls.shown.len() == 2));
+
+        notifier.on_event(&event(TimerEventKind::Acknowledged, 8));
+        assert!(wait_for(&calls, |calls| calls.closed == [2]));
+    }
+}
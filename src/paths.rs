@@ -0,0 +1,174 @@
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+use timers_core::persistence::backup_path;
+
+use crate::{
+    profiles::{profile_config_file, profiles_dir, DEFAULT_PROFILE},
+    settings::{project_config_file, system_config_file, Settings, SettingsSources},
+    shared_save::{lock_path, SaveFileLock},
+    status::live_state_path,
+};
+
+const APP_DIR: &str = "timers_tui";
+const SAVE_FILE_NAME: &str = "saved_timers.json";
+
+// An XDG variable wins on every platform when it is set to an absolute path,
+// otherwise the platform's usual directory
+fn xdg_dir(variable: &str, fallback: Option<PathBuf>) -> Option<PathBuf> {
+    match env::var_os(variable).map(PathBuf::from) {
+        Some(dir) if dir.is_absolute() => Some(dir),
+        _ => fallback,
+    }
+    .map(|dir| dir.join(APP_DIR))
+}
+
+// Settings, which the user edits
+pub fn config_dir() -> Option<PathBuf> {
+    xdg_dir("XDG_CONFIG_HOME", dirs::config_dir())
+}
+
+// Saved timers and their backups
+pub fn data_dir() -> Option<PathBuf> {
+    xdg_dir("XDG_DATA_HOME", dirs::data_dir())
+}
+
+// Logs, crash reports and anything else worth keeping that isn't data. Only
+// Linux has a state directory, elsewhere it goes with the local data
+pub fn state_dir() -> Option<PathBuf> {
+    xdg_dir(
+        "XDG_STATE_HOME",
+        dirs::state_dir().or_else(dirs::data_local_dir),
+    )
+}
+
+// Files that only matter while an instance is running
+pub fn runtime_dir() -> Option<PathBuf> {
+    xdg_dir(
+        "XDG_RUNTIME_DIR",
+        dirs::runtime_dir().or_else(dirs::cache_dir),
+    )
+}
+
+// The state directory, created if it isn't there yet
+pub fn ensure_state_dir() -> Option<PathBuf> {
+    let path = state_dir()?;
+
+    fs::create_dir_all(&path).ok()?;
+
+    Some(path)
+}
+
+// Where a profile's timers are saved unless its settings say otherwise.
+// Named profiles have a directory under the data directory, their settings
+// are under the config directory
+pub fn default_save_file(profile: &str) -> Option<PathBuf> {
+    match profile {
+        DEFAULT_PROFILE => Some(data_dir()?.join(SAVE_FILE_NAME)),
+        profile => Some(profile_data_dir()?.join(profile).join(SAVE_FILE_NAME)),
+    }
+}
+
+pub fn profile_data_dir() -> Option<PathBuf> {
+    Some(data_dir()?.join("profiles"))
+}
+
+// Where saved timers went before they moved to the data directory
+fn legacy_save_file(profile: &str) -> Option<PathBuf> {
+    let dir = config_dir()?;
+
+    match profile {
+        DEFAULT_PROFILE => Some(dir.join(SAVE_FILE_NAME)),
+        profile => Some(dir.join("profiles").join(profile).join(SAVE_FILE_NAME)),
+    }
+}
+
+fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    // Renaming fails between filesystems, where it has to be copied
+    if fs::rename(from, to).is_err() {
+        fs::copy(from, to)?;
+        fs::remove_file(from)?;
+    }
+
+    Ok(())
+}
+
+// Moves a profile's saved timers and their backups from the config
+// directory, where they used to be, to the data directory. Only when the
+// profile saves to the default place and nothing is there yet, so it happens
+// once. An older instance still using the old file holds it back until the
+// next start. Returns where the timers were moved from
+pub fn migrate_legacy_save_file(profile: &str, save_file: &Path) -> Option<PathBuf> {
+    if default_save_file(profile)? != save_file || save_file.exists() {
+        return None;
+    }
+
+    let old = legacy_save_file(profile)?;
+
+    if !old.is_file() {
+        return None;
+    }
+
+    // Only held while moving, and the lock file goes with the old file
+    let Ok(Some(_lock)) = SaveFileLock::acquire(&old) else {
+        return None;
+    };
+
+    move_file(&old, save_file).ok()?;
+
+    let _ = fs::remove_file(lock_path(&old));
+
+    for number in 1.. {
+        let backup = backup_path(&old, number);
+
+        if !backup.is_file() || move_file(&backup, &backup_path(save_file, number)).is_err() {
+            break;
+        }
+    }
+
+    Some(old)
+}
+
+// Every file and directory in use for a profile, for `paths`
+pub fn resolved_paths(
+    sources: &SettingsSources,
+    settings: &Settings,
+    profile: &str,
+    save_file: &Option<PathBuf>,
+) -> Vec<(&'static str, Option<PathBuf>)> {
+    let mut paths = vec![
+        ("System config", Some(system_config_file())),
+        ("Config file", sources.config_file.clone()),
+    ];
+
+    if profile != DEFAULT_PROFILE {
+        paths.push(("Profile config", profile_config_file(profile)));
+    }
+
+    paths.extend([
+        ("Project config", project_config_file()),
+        ("Save file", save_file.clone()),
+        (
+            "Newest backup",
+            save_file
+                .as_ref()
+                .map(|save_file| backup_path(save_file, 1)),
+        ),
+        ("Lock file", save_file.as_deref().map(lock_path)),
+        ("Config dir", config_dir()),
+        ("Data dir", data_dir()),
+        ("Profiles config dir", profiles_dir()),
+        ("Profiles data dir", profile_data_dir()),
+        ("State dir", state_dir()),
+        ("Live state", live_state_path(profile)),
+        ("Calendar file", settings.calendar_file.clone()),
+    ]);
+
+    paths
+}
@@ -0,0 +1,58 @@
+use std::time::Duration;
+
+use crate::settings::Settings;
+
+#[derive(Clone)]
+pub struct Preset {
+    pub description: String,
+    pub length: Duration,
+}
+
+// Parses lengths like "90s", "3m", "1h30m" or a bare number of seconds
+pub fn parse_length(length: &str) -> Option<Duration> {
+    let length = length.trim();
+
+    if let Ok(seconds) = length.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let mut total: u64 = 0;
+    let mut number = String::new();
+
+    for c in length.chars() {
+        match c {
+            '0'..='9' => number.push(c),
+            'h' | 'm' | 's' => {
+                let value: u64 = number.parse().ok()?;
+                number.clear();
+
+                let seconds = match c {
+                    'h' => value.checked_mul(3600)?,
+                    'm' => value.checked_mul(60)?,
+                    _ => value,
+                };
+
+                total = total.checked_add(seconds)?;
+            }
+            c if c.is_whitespace() => (),
+            _ => return None,
+        }
+    }
+
+    if !number.is_empty() || total == 0 {
+        return None;
+    }
+
+    Some(Duration::from_secs(total))
+}
+
+pub fn load_presets(settings: &Settings) -> Vec<Preset> {
+    settings
+        .presets
+        .iter()
+        .map(|preset| Preset {
+            description: preset.description.clone(),
+            length: preset.length.0,
+        })
+        .collect()
+}
@@ -0,0 +1,73 @@
+use std::{fs, path::PathBuf};
+
+use crate::{
+    paths::{config_dir, default_save_file, profile_data_dir},
+    settings::read_settings_file,
+};
+
+// The profile without a directory of its own, using the usual config and save
+// file
+pub const DEFAULT_PROFILE: &str = "default";
+
+// Every other profile is a directory here, holding an optional config.toml
+// with settings that override the main config file. Its saved timers are in
+// a directory of the same name under the data directory
+pub fn profiles_dir() -> Option<PathBuf> {
+    Some(config_dir()?.join("profiles"))
+}
+
+pub fn is_valid_profile_name(name: &str) -> bool {
+    !name.is_empty()
+        && name != "."
+        && name != ".."
+        && !name.contains(['/', '\\'])
+        && !name.starts_with('.')
+}
+
+pub fn profile_config_file(name: &str) -> Option<PathBuf> {
+    let mut path = profiles_dir()?;
+    path.push(name);
+    path.push("config.toml");
+
+    Some(path)
+}
+
+// A profile's own save_file setting, otherwise saved_timers.json in its data
+// directory. The main config's save_file belongs to the default profile
+pub fn profile_save_file(name: &str) -> Option<PathBuf> {
+    let profile_config = profile_config_file(name)?;
+
+    // A broken profile config is reported when the settings are loaded
+    let own_save_file = read_settings_file(&profile_config)
+        .ok()
+        .flatten()
+        .and_then(|settings| settings.get("save_file")?.as_str().map(PathBuf::from));
+
+    match own_save_file {
+        Some(save_file) => Some(save_file),
+        None => default_save_file(name),
+    }
+}
+
+// The default profile first, then the rest by name. A profile can have
+// settings, saved timers or both
+pub fn list_profiles() -> Vec<String> {
+    let mut profiles: Vec<String> = [profiles_dir(), profile_data_dir()]
+        .into_iter()
+        .flatten()
+        .filter_map(|dir| fs::read_dir(dir).ok())
+        .flat_map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.path().is_dir())
+                .filter_map(|entry| entry.file_name().into_string().ok())
+                .filter(|name| is_valid_profile_name(name) && name != DEFAULT_PROFILE)
+        })
+        .collect();
+
+    profiles.sort();
+    profiles.dedup();
+    profiles.insert(0, String::from(DEFAULT_PROFILE));
+
+    profiles
+}
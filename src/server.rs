@@ -0,0 +1,479 @@
+use std::{
+    io::Write,
+    sync::{
+        mpsc::{channel, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use timers_core::{
+    change_key, SerializeableTimerParts, TimerEvent, TimerEventListener, TimerSnapshot,
+};
+
+use crate::app::TimerCommand;
+
+// How long a request waits for the event loop to pick up a new timer
+const ADD_TIMEOUT: Duration = Duration::from_secs(2);
+
+type Subscribers = Arc<Mutex<Vec<Sender<String>>>>;
+
+// An HTTP API on localhost for the same timers the TUI shows:
+//
+//   GET    /timers                  list every timer
+//   GET    /timers/{id}             a single timer
+//   POST   /timers                  add a timer, the body is a saved timer
+//   POST   /timers/{id}/start       start a timer, also pause and reset
+//   DELETE /timers/{id}             delete a timer
+//   GET    /events                  server-sent events for every change
+//
+// Requests must be addressed to 127.0.0.1 or localhost on this port, and a
+// request with a body must send it as application/json
+pub struct HttpServer {
+    timers: Arc<Mutex<Vec<TimerSnapshot>>>,
+    subscribers: Subscribers,
+    last_change_key: String,
+}
+
+impl HttpServer {
+    pub fn start(
+        port: u16,
+        command_sender: Sender<TimerCommand>,
+    ) -> Result<HttpServer, Box<dyn std::error::Error + Send + Sync>> {
+        let server = Server::http(("127.0.0.1", port))?;
+
+        let timers = Arc::new(Mutex::new(Vec::new()));
+        let subscribers: Subscribers = Arc::new(Mutex::new(Vec::new()));
+
+        let server_timers = Arc::clone(&timers);
+        let server_subscribers = Arc::clone(&subscribers);
+
+        thread::spawn(move || {
+            for request in server.incoming_requests() {
+                let timers = Arc::clone(&server_timers);
+                let subscribers = Arc::clone(&server_subscribers);
+                let command_sender = command_sender.clone();
+
+                // Event streams hold their request open, so every request
+                // gets its own thread
+                thread::spawn(move || {
+                    handle_request(request, port, &timers, &subscribers, &command_sender)
+                });
+            }
+        });
+
+        Ok(HttpServer {
+            timers,
+            subscribers,
+            last_change_key: String::new(),
+        })
+    }
+
+    fn broadcast(&self, event: &str, data: &str) {
+        let message = format!("event: {}\ndata: {}\n\n", event, data);
+
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|subscriber| subscriber.send(message.clone()).is_ok());
+    }
+}
+
+impl TimerEventListener for HttpServer {
+    fn on_event(&mut self, event: &TimerEvent) {
+        if let Ok(json) = serde_json::to_string(event) {
+            self.broadcast(event.kind.name(), &json);
+        }
+    }
+
+    fn on_update(&mut self, timers: &[TimerSnapshot]) {
+        *self.timers.lock().unwrap() = timers.to_vec();
+
+        // Not on every tick, only when something other than a countdown
+        // changed
+        let key = change_key(timers);
+
+        if key != self.last_change_key {
+            if let Ok(json) = serde_json::to_string(timers) {
+                self.broadcast("timers", &json);
+            }
+
+            self.last_change_key = key;
+        }
+    }
+}
+
+fn json_response(status: u16, body: String) -> Response<std::io::Cursor<Vec<u8>>> {
+    Response::from_string(body)
+        .with_status_code(status)
+        .with_header(Header::from_bytes("Content-Type", "application/json").unwrap())
+}
+
+fn empty_response(status: u16) -> Response<std::io::Empty> {
+    Response::empty(status)
+}
+
+fn header_value<'a>(request: &'a Request, name: &'static str) -> Option<&'a str> {
+    request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv(name))
+        .map(|header| header.value.as_str())
+}
+
+// Any web page can send simple requests to localhost, and DNS rebinding can
+// point a page's own host name here. So only requests addressed to this port
+// by a local name and not made from another page are served, and a body must
+// be JSON, which a page can't send without a preflight we never answer
+fn rejection(request: &Request, port: u16) -> Option<(u16, &'static str)> {
+    let hosts = [format!("127.0.0.1:{}", port), format!("localhost:{}", port)];
+
+    let is_local = |host: &str| hosts.iter().any(|local| local.eq_ignore_ascii_case(host));
+
+    if !header_value(request, "Host").is_some_and(is_local) {
+        return Some((
+            403,
+            "the Host header must be 127.0.0.1 or localhost on this port",
+        ));
+    }
+
+    if let Some(origin) = header_value(request, "Origin") {
+        if !origin.strip_prefix("http://").is_some_and(is_local) {
+            return Some((403, "requests from other origins are not allowed"));
+        }
+    }
+
+    let has_body = match request.body_length() {
+        Some(length) => length > 0,
+        None => header_value(request, "Transfer-Encoding").is_some(),
+    };
+
+    let is_json = header_value(request, "Content-Type").is_some_and(|content_type| {
+        let media_type = content_type.split(';').next().unwrap_or_default();
+
+        media_type.trim().eq_ignore_ascii_case("application/json")
+    });
+
+    if has_body && !is_json {
+        return Some((415, "the body must be application/json"));
+    }
+
+    None
+}
+
+fn handle_request(
+    mut request: Request,
+    port: u16,
+    timers: &Mutex<Vec<TimerSnapshot>>,
+    subscribers: &Subscribers,
+    command_sender: &Sender<TimerCommand>,
+) {
+    if let Some((status, error)) = rejection(&request, port) {
+        let _ = request.respond(json_response(
+            status,
+            serde_json::json!({ "error": error }).to_string(),
+        ));
+        return;
+    }
+
+    let path: Vec<String> = request
+        .url()
+        .split('?')
+        .next()
+        .unwrap_or_default()
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .map(String::from)
+        .collect();
+    let path: Vec<&str> = path.iter().map(String::as_str).collect();
+
+    let method = request.method().clone();
+
+    let find_timer = |id: &str| -> Option<TimerSnapshot> {
+        let id: u64 = id.parse().ok()?;
+
+        timers
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|timer| timer.id == id)
+            .cloned()
+    };
+
+    let _ = match (&method, path.as_slice()) {
+        (Method::Get, ["timers"]) => {
+            let json = serde_json::to_string(&*timers.lock().unwrap()).unwrap_or_default();
+
+            request.respond(json_response(200, json))
+        }
+        (Method::Get, ["timers", id]) => match find_timer(id) {
+            Some(timer) => {
+                request.respond(json_response(200, serde_json::to_string(&timer).unwrap()))
+            }
+            None => request.respond(empty_response(404)),
+        },
+        (Method::Post, ["timers"]) => {
+            let mut body = String::new();
+            let _ = request.as_reader().read_to_string(&mut body);
+
+            match serde_json::from_str::<SerializeableTimerParts>(&body) {
+                Ok(parts) => {
+                    let (reply_sender, reply_receiver) = channel();
+                    let _ = command_sender.send(TimerCommand::Add(parts, reply_sender));
+
+                    match reply_receiver.recv_timeout(ADD_TIMEOUT) {
+                        Ok(timer) => request
+                            .respond(json_response(201, serde_json::to_string(&timer).unwrap())),
+                        Err(_) => request.respond(empty_response(503)),
+                    }
+                }
+                Err(e) => request.respond(json_response(
+                    400,
+                    serde_json::json!({ "error": e.to_string() }).to_string(),
+                )),
+            }
+        }
+        (Method::Post, ["timers", id, action]) => match find_timer(id) {
+            Some(timer) => {
+                let command = match *action {
+                    "start" => Some(TimerCommand::Start(timer.id)),
+                    "pause" => Some(TimerCommand::Pause(timer.id)),
+                    "reset" => Some(TimerCommand::Reset(timer.id)),
+                    _ => None,
+                };
+
+                match command {
+                    Some(command) => {
+                        let _ = command_sender.send(command);
+                        request.respond(empty_response(202))
+                    }
+                    None => request.respond(empty_response(404)),
+                }
+            }
+            None => request.respond(empty_response(404)),
+        },
+        (Method::Delete, ["timers", id]) => match find_timer(id) {
+            Some(timer) => {
+                let _ = command_sender.send(TimerCommand::Delete(timer.id));
+                request.respond(empty_response(202))
+            }
+            None => request.respond(empty_response(404)),
+        },
+        (Method::Get, ["events"]) => {
+            stream_events(request, subscribers);
+            Ok(())
+        }
+        (_, ["timers", ..]) | (_, ["events"]) => request.respond(empty_response(405)),
+        _ => request.respond(empty_response(404)),
+    };
+}
+
+fn stream_events(request: Request, subscribers: &Subscribers) {
+    let (sender, receiver) = channel::<String>();
+
+    subscribers.lock().unwrap().push(sender);
+
+    let mut writer = request.into_writer();
+
+    let headers = "HTTP/1.1 200 OK\r\n\
+        Content-Type: text/event-stream\r\n\
+        Cache-Control: no-cache\r\n\
+        Connection: keep-alive\r\n\r\n";
+
+    if writer.write_all(headers.as_bytes()).is_err() || writer.flush().is_err() {
+        return;
+    }
+
+    // Dropping the receiver when the client goes away takes this subscriber
+    // out of the list on the next broadcast
+    for message in receiver {
+        if writer.write_all(message.as_bytes()).is_err() || writer.flush().is_err() {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::TcpListener,
+        sync::mpsc::{Receiver, RecvTimeoutError},
+    };
+
+    use timers_core::TimerState;
+
+    use super::*;
+
+    fn free_port() -> u16 {
+        TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port()
+    }
+
+    fn snapshot(id: u64, description: &str) -> TimerSnapshot {
+        TimerSnapshot {
+            id,
+            state: TimerState::Stopped,
+            parts: SerializeableTimerParts {
+                length: Duration::from_secs(180),
+                time_left: Duration::from_secs(180),
+                description: String::from(description),
+                webhooks: false,
+                uid: id,
+            },
+        }
+    }
+
+    // A server showing a timer with id 4, and where its commands go
+    fn start() -> (HttpServer, String, Receiver<TimerCommand>) {
+        let port = free_port();
+        let (command_sender, command_receiver) = channel();
+
+        let mut server = HttpServer::start(port, command_sender).unwrap();
+        server.on_update(&[snapshot(4, "Tea")]);
+
+        (
+            server,
+            format!("http://127.0.0.1:{}", port),
+            command_receiver,
+        )
+    }
+
+    fn status(response: Result<ureq::Response, ureq::Error>) -> u16 {
+        match response {
+            Ok(response) => response.status(),
+            Err(ureq::Error::Status(status, _)) => status,
+            Err(error) => panic!("{}", error),
+        }
+    }
+
+    fn json(response: ureq::Response) -> serde_json::Value {
+        serde_json::from_str(&response.into_string().unwrap()).unwrap()
+    }
+
+    fn next_command(commands: &Receiver<TimerCommand>) -> TimerCommand {
+        commands.recv_timeout(Duration::from_secs(2)).unwrap()
+    }
+
+    #[test]
+    fn lists_and_finds_timers() {
+        let (_server, url, _commands) = start();
+
+        let timers = json(ureq::get(&format!("{}/timers", url)).call().unwrap());
+        assert_eq!(timers[0]["id"], 4);
+        assert_eq!(timers[0]["description"], "Tea");
+
+        let timer = json(ureq::get(&format!("{}/timers/4", url)).call().unwrap());
+        assert_eq!(timer["state"], "Stopped");
+
+        assert_eq!(status(ureq::get(&format!("{}/timers/5", url)).call()), 404);
+        assert_eq!(status(ureq::get(&format!("{}/nothing", url)).call()), 404);
+        assert_eq!(status(ureq::put(&format!("{}/timers", url)).call()), 405);
+    }
+
+    #[test]
+    fn starts_pauses_resets_and_deletes_without_a_body() {
+        let (_server, url, commands) = start();
+
+        for action in ["start", "pause", "reset"] {
+            let response = ureq::post(&format!("{}/timers/4/{}", url, action)).call();
+            assert_eq!(status(response), 202);
+        }
+
+        assert!(matches!(next_command(&commands), TimerCommand::Start(4)));
+        assert!(matches!(next_command(&commands), TimerCommand::Pause(4)));
+        assert!(matches!(next_command(&commands), TimerCommand::Reset(4)));
+
+        assert_eq!(
+            status(ureq::delete(&format!("{}/timers/4", url)).call()),
+            202
+        );
+        assert!(matches!(next_command(&commands), TimerCommand::Delete(4)));
+
+        assert_eq!(
+            status(ureq::post(&format!("{}/timers/4/explode", url)).call()),
+            404
+        );
+        assert_eq!(
+            status(ureq::delete(&format!("{}/timers/5", url)).call()),
+            404
+        );
+        assert!(matches!(
+            commands.recv_timeout(Duration::from_millis(100)),
+            Err(RecvTimeoutError::Timeout)
+        ));
+    }
+
+    #[test]
+    fn adds_timers_sent_as_json() {
+        let (_server, url, commands) = start();
+
+        // Stands in for the event loop
+        thread::spawn(move || {
+            if let Ok(TimerCommand::Add(parts, reply)) = commands.recv() {
+                let _ = reply.send(TimerSnapshot {
+                    id: 5,
+                    state: TimerState::Stopped,
+                    parts,
+                });
+            }
+        });
+
+        let response = ureq::post(&format!("{}/timers", url))
+            .set("Content-Type", "application/json; charset=utf-8")
+            .send_string(&serde_json::to_string(&snapshot(0, "Pizza").parts).unwrap())
+            .unwrap();
+
+        assert_eq!(response.status(), 201);
+
+        let timer = json(response);
+        assert_eq!(timer["id"], 5);
+        assert_eq!(timer["description"], "Pizza");
+
+        let response = ureq::post(&format!("{}/timers", url))
+            .set("Content-Type", "application/json")
+            .send_string("{}");
+        assert_eq!(status(response), 400);
+    }
+
+    #[test]
+    fn refuses_bodies_that_are_not_json() {
+        let (_server, url, commands) = start();
+
+        let response = ureq::post(&format!("{}/timers", url))
+            .set("Content-Type", "text/plain")
+            .send_string("{}");
+        assert_eq!(status(response), 415);
+
+        let response = ureq::post(&format!("{}/timers/4/start", url)).send_string("start");
+        assert_eq!(status(response), 415);
+
+        assert!(commands.try_recv().is_err());
+    }
+
+    #[test]
+    fn refuses_other_hosts_and_origins() {
+        let (_server, url, commands) = start();
+
+        let response = ureq::post(&format!("{}/timers/4/start", url))
+            .set("Origin", "http://example.com")
+            .call();
+        assert_eq!(status(response), 403);
+
+        let response = ureq::get(&format!("{}/timers", url))
+            .set("Host", "example.com")
+            .call();
+        assert_eq!(status(response), 403);
+
+        let response = ureq::post(&format!("{}/timers/4/start", url))
+            .set("Origin", &url)
+            .call();
+        assert_eq!(status(response), 202);
+        assert!(matches!(next_command(&commands), TimerCommand::Start(4)));
+    }
+}
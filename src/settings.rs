@@ -0,0 +1,689 @@
+use std::{
+    env,
+    fmt::{self, Display},
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::{mpsc::Sender, Arc, Mutex},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use serde::{
+    de::{self, Unexpected, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use serde_json::Value;
+use timers_core::persistence::{DEFAULT_AUTOSAVE_INTERVAL, DEFAULT_BACKUPS};
+
+use crate::{
+    app::TimerCommand,
+    hooks::Hooks,
+    paths::config_dir,
+    presets::parse_length,
+    profiles::{profile_config_file, DEFAULT_PROFILE},
+    webhooks::WebhookSettings,
+};
+
+// A length written like "90s", "3m", "1h30m" or a number of seconds
+#[derive(Clone, Copy)]
+pub struct Length(pub Duration);
+
+impl<'de> Deserialize<'de> for Length {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Length, D::Error> {
+        struct LengthVisitor;
+
+        impl Visitor<'_> for LengthVisitor {
+            type Value = Length;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a length like \"90s\", \"3m\" or \"1h30m\"")
+            }
+
+            fn visit_u64<E: de::Error>(self, seconds: u64) -> Result<Length, E> {
+                Ok(Length(Duration::from_secs(seconds)))
+            }
+
+            fn visit_i64<E: de::Error>(self, seconds: i64) -> Result<Length, E> {
+                match u64::try_from(seconds) {
+                    Ok(seconds) => self.visit_u64(seconds),
+                    Err(_) => Err(E::invalid_value(Unexpected::Signed(seconds), &self)),
+                }
+            }
+
+            fn visit_str<E: de::Error>(self, length: &str) -> Result<Length, E> {
+                parse_length(length)
+                    .map(Length)
+                    .ok_or_else(|| E::invalid_value(Unexpected::Str(length), &self))
+            }
+        }
+
+        deserializer.deserialize_any(LengthVisitor)
+    }
+}
+
+// Written back the way it would be in a config file, like "1h30m"
+impl Serialize for Length {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let seconds = self.0.as_secs();
+
+        if seconds == 0 {
+            return serializer.serialize_str("0");
+        }
+
+        let mut length = String::new();
+
+        for (count, unit) in [
+            (seconds / 3600, 'h'),
+            (seconds / 60 % 60, 'm'),
+            (seconds % 60, 's'),
+        ] {
+            if count > 0 {
+                length.push_str(&format!("{}{}", count, unit));
+            }
+        }
+
+        serializer.serialize_str(&length)
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct PresetSettings {
+    pub description: String,
+    pub length: Length,
+}
+
+// Everything the config file can set. Unknown keys are an error, so a typo
+// doesn't silently leave a setting at its default
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct Settings {
+    pub save_file: Option<PathBuf>,
+    pub presets: Vec<PresetSettings>,
+    pub snooze_length: Length,
+    pub alarm_sound: Option<PathBuf>,
+    pub autosave: bool,
+    // Zero only saves when something changes
+    pub autosave_interval: Length,
+    pub backups: u32,
+    pub desktop_notifications: bool,
+    pub terminal_notifications: bool,
+    pub window_title: bool,
+    pub hooks: Hooks,
+    pub webhooks: Option<WebhookSettings>,
+    pub http_port: Option<u16>,
+    pub metrics_port: Option<u16>,
+    pub calendar_file: Option<PathBuf>,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            save_file: None,
+            presets: Vec::new(),
+            snooze_length: Length(Duration::from_secs(300)),
+            alarm_sound: None,
+            autosave: true,
+            autosave_interval: Length(DEFAULT_AUTOSAVE_INTERVAL),
+            backups: DEFAULT_BACKUPS,
+            desktop_notifications: true,
+            terminal_notifications: true,
+            window_title: true,
+            hooks: Hooks::default(),
+            webhooks: None,
+            http_port: None,
+            metrics_port: None,
+            calendar_file: None,
+        }
+    }
+}
+
+// A bad setting, and the file, environment variable or flag it came from
+#[derive(Debug)]
+pub struct SettingsError {
+    pub source: String,
+    pub message: String,
+}
+
+impl Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.source, self.message)
+    }
+}
+
+pub fn default_config_file() -> Option<PathBuf> {
+    Some(config_dir()?.join("config.toml"))
+}
+
+// Checks a config file on its own, so errors point at its lines, and returns
+// it as a value to layer with the others. None if there is no such file
+pub fn read_settings_file(path: &Path) -> Result<Option<Value>, SettingsError> {
+    let error = |message: String| SettingsError {
+        source: path.display().to_string(),
+        message,
+    };
+
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(error(e.to_string())),
+    };
+
+    let extension = path.extension().and_then(|extension| extension.to_str());
+
+    let value = match extension {
+        Some("json") => {
+            serde_json::from_str::<Settings>(&contents).map_err(|e| error(e.to_string()))?;
+            serde_json::from_str::<Value>(&contents).map_err(|e| error(e.to_string()))?
+        }
+        Some("yaml" | "yml") => {
+            serde_yaml::from_str::<Settings>(&contents).map_err(|e| error(e.to_string()))?;
+            serde_yaml::from_str::<Value>(&contents).map_err(|e| error(e.to_string()))?
+        }
+        _ => {
+            toml::from_str::<Settings>(&contents).map_err(|e| error(e.to_string()))?;
+            let table =
+                toml::from_str::<toml::Table>(&contents).map_err(|e| error(e.to_string()))?;
+            serde_json::to_value(table).map_err(|e| error(e.to_string()))?
+        }
+    };
+
+    Ok(Some(value))
+}
+
+// Tables are merged key by key, anything else on top replaces what is below
+fn merge(below: &mut Value, above: Value) {
+    match (below, above) {
+        (Value::Object(below), Value::Object(above)) => {
+            for (key, value) in above {
+                match below.get_mut(&key) {
+                    Some(below) => merge(below, value),
+                    None => {
+                        below.insert(key, value);
+                    }
+                }
+            }
+        }
+        (below, above) => *below = above,
+    }
+}
+
+const SYSTEM_CONFIG_FILE: &str = "/etc/timers_tui/config.toml";
+const PROJECT_CONFIG_FILE: &str = ".timers_tui.toml";
+const ENV_PREFIX: &str = "TIMERS_TUI_";
+
+// Where settings come from, other than the profile in use
+pub struct SettingsSources {
+    pub config_file: Option<PathBuf>,
+    // A config file given on the command line has to be there
+    pub config_file_required: bool,
+    pub save_file: Option<PathBuf>,
+    // Dotted keys and values from --set, applied last
+    pub overrides: Vec<(String, Value)>,
+}
+
+// One source of settings, in the shape of a config file
+pub struct Layer {
+    pub source: String,
+    pub value: Value,
+}
+
+pub fn system_config_file() -> PathBuf {
+    PathBuf::from(SYSTEM_CONFIG_FILE)
+}
+
+// The nearest .timers_tui.toml in the current directory or one above it
+pub fn project_config_file() -> Option<PathBuf> {
+    let current_dir = env::current_dir().ok()?;
+
+    current_dir
+        .ancestors()
+        .map(|dir| dir.join(PROJECT_CONFIG_FILE))
+        .find(|path| path.is_file())
+}
+
+// Every config file a profile's settings could come from, whether it is there
+// yet or not
+pub fn settings_files(sources: &SettingsSources, profile: &str) -> Vec<PathBuf> {
+    let mut files = vec![PathBuf::from(SYSTEM_CONFIG_FILE)];
+
+    files.extend(sources.config_file.clone());
+
+    if profile != DEFAULT_PROFILE {
+        files.extend(profile_config_file(profile));
+    }
+
+    match project_config_file() {
+        Some(project_config) => files.push(project_config),
+        None => files.extend(
+            env::current_dir()
+                .ok()
+                .map(|dir| dir.join(PROJECT_CONFIG_FILE)),
+        ),
+    }
+
+    files
+}
+
+// A dotted key like hooks.alarm as the tables it would be in a config file
+fn nested(key: &str, value: Value) -> Value {
+    key.rsplit('.').fold(value, |value, part| {
+        let mut table = serde_json::Map::new();
+        table.insert(String::from(part), value);
+        Value::Object(table)
+    })
+}
+
+// Values are read as TOML, so numbers, booleans and arrays work, and
+// anything that isn't valid TOML is taken as a plain string
+fn parse_value(value: &str) -> Value {
+    match toml::from_str::<toml::Table>(&format!("value = {}", value)) {
+        Ok(mut table) => table
+            .remove("value")
+            .and_then(|value| serde_json::to_value(value).ok())
+            .unwrap_or_else(|| Value::String(String::from(value))),
+        Err(_) => Value::String(String::from(value)),
+    }
+}
+
+// For --set, KEY=VALUE with a dotted key
+pub fn parse_override(setting: &str) -> Result<(String, Value), String> {
+    match setting.split_once('=') {
+        Some((key, value)) if !key.trim().is_empty() => {
+            Ok((String::from(key.trim()), parse_value(value)))
+        }
+        _ => Err(String::from("expected KEY=VALUE")),
+    }
+}
+
+// Checks a layer that isn't a file on its own, so a bad value is blamed on
+// the variable or flag it came from
+fn checked_layer(source: String, value: Value) -> Result<Layer, SettingsError> {
+    match serde_json::from_value::<Settings>(value.clone()) {
+        Ok(_) => Ok(Layer { source, value }),
+        Err(e) => Err(SettingsError {
+            source,
+            message: e.to_string(),
+        }),
+    }
+}
+
+// TIMERS_TUI_SNOOZE_LENGTH sets snooze_length, and a double underscore goes
+// into a table, so TIMERS_TUI_HOOKS__ALARM sets hooks.alarm
+fn environment_layers() -> Result<Vec<Layer>, SettingsError> {
+    let mut variables = Vec::new();
+
+    // Other programs' variables can be any bytes, only ours have to be UTF-8
+    for (name, value) in env::vars_os() {
+        let Some(name) = name.to_str().filter(|name| name.starts_with(ENV_PREFIX)) else {
+            continue;
+        };
+
+        let Some(value) = value.to_str() else {
+            return Err(SettingsError {
+                source: String::from(name),
+                message: String::from("The value is not valid UTF-8"),
+            });
+        };
+
+        variables.push((String::from(name), String::from(value)));
+    }
+
+    variables.sort();
+
+    variables
+        .into_iter()
+        .map(|(name, value)| {
+            let key = name[ENV_PREFIX.len()..].to_lowercase().replace("__", ".");
+            checked_layer(name, nested(&key, parse_value(&value)))
+        })
+        .collect()
+}
+
+// Settings a .timers_tui.toml can't change, since it is picked up from
+// whatever directory timers_tui is started in: they run commands, send timers
+// to other machines or choose which files are read and written
+const PROJECT_FORBIDDEN_SETTINGS: [&str; 5] = [
+    "save_file",
+    "alarm_sound",
+    "calendar_file",
+    "hooks",
+    "webhooks",
+];
+
+fn project_layer(path: &Path, value: Value) -> Result<Layer, SettingsError> {
+    let source = path.display().to_string();
+
+    let forbidden: Vec<&str> = PROJECT_FORBIDDEN_SETTINGS
+        .into_iter()
+        .filter(|key| value.get(key).is_some())
+        .collect();
+
+    if !forbidden.is_empty() {
+        return Err(SettingsError {
+            source,
+            message: format!(
+                "{} can't be set in a {}, only in your own config file",
+                forbidden.join(", "),
+                PROJECT_CONFIG_FILE
+            ),
+        });
+    }
+
+    Ok(Layer { source, value })
+}
+
+// Every source of settings for a profile, lowest first: the built-in
+// defaults, the system-wide config, the user's config file, the profile's
+// own config.toml, the nearest .timers_tui.toml, TIMERS_TUI_* environment
+// variables and the command line
+pub fn settings_layers(
+    sources: &SettingsSources,
+    profile: &str,
+) -> Result<Vec<Layer>, SettingsError> {
+    let mut layers = vec![Layer {
+        source: String::from("default"),
+        value: serde_json::to_value(Settings::default()).map_err(|e| SettingsError {
+            source: String::from("default"),
+            message: e.to_string(),
+        })?,
+    }];
+
+    let mut files = vec![(PathBuf::from(SYSTEM_CONFIG_FILE), false)];
+
+    if let Some(config_file) = &sources.config_file {
+        files.push((config_file.clone(), sources.config_file_required));
+    }
+
+    if profile != DEFAULT_PROFILE {
+        if let Some(profile_config) = profile_config_file(profile) {
+            files.push((profile_config, false));
+        }
+    }
+
+    for (path, required) in files {
+        match read_settings_file(&path)? {
+            Some(value) => layers.push(Layer {
+                source: path.display().to_string(),
+                value,
+            }),
+            None if required => {
+                return Err(SettingsError {
+                    source: path.display().to_string(),
+                    message: String::from("No such file"),
+                })
+            }
+            None => (),
+        }
+    }
+
+    if let Some(project_config) = project_config_file() {
+        if let Some(value) = read_settings_file(&project_config)? {
+            layers.push(project_layer(&project_config, value)?);
+        }
+    }
+
+    layers.extend(environment_layers()?);
+
+    if let Some(save_file) = &sources.save_file {
+        layers.push(Layer {
+            source: String::from("--save-file"),
+            value: nested("save_file", Value::String(save_file.display().to_string())),
+        });
+    }
+
+    for (key, value) in &sources.overrides {
+        layers.push(checked_layer(
+            format!("--set {}", key),
+            nested(key, value.clone()),
+        )?);
+    }
+
+    Ok(layers)
+}
+
+fn merge_layers(layers: &[Layer]) -> Value {
+    let mut merged = Value::Object(Default::default());
+
+    for layer in layers {
+        merge(&mut merged, layer.value.clone());
+    }
+
+    merged
+}
+
+pub fn load_settings(sources: &SettingsSources, profile: &str) -> Result<Settings, SettingsError> {
+    let layers = settings_layers(sources, profile)?;
+
+    serde_json::from_value(merge_layers(&layers)).map_err(|e| SettingsError {
+        source: layers
+            .last()
+            .map(|layer| layer.source.clone())
+            .unwrap_or_default(),
+        message: e.to_string(),
+    })
+}
+
+// The dotted keys of every setting with its value
+fn flatten(prefix: &str, value: &Value, settings: &mut Vec<(String, Value)>) {
+    match value {
+        Value::Object(table) => {
+            for (key, value) in table {
+                let key = match prefix {
+                    "" => key.clone(),
+                    _ => format!("{}.{}", prefix, key),
+                };
+
+                flatten(&key, value, settings);
+            }
+        }
+        _ => settings.push((String::from(prefix), value.clone())),
+    }
+}
+
+// Each effective setting, its value and the source it came from, for
+// `config show`
+pub fn show_settings(
+    sources: &SettingsSources,
+    profile: &str,
+) -> Result<Vec<(String, Value, String)>, SettingsError> {
+    load_settings(sources, profile)?;
+
+    let layers = settings_layers(sources, profile)?;
+    let mut settings = Vec::new();
+
+    flatten("", &merge_layers(&layers), &mut settings);
+
+    Ok(settings
+        .into_iter()
+        .map(|(key, value)| {
+            let pointer = format!("/{}", key.replace('.', "/"));
+
+            let source = layers
+                .iter()
+                .rev()
+                .find(|layer| layer.value.pointer(&pointer).is_some())
+                .map(|layer| layer.source.clone())
+                .unwrap_or_default();
+
+            (key, value, source)
+        })
+        .collect())
+}
+
+// Written by `config init`. Every setting is commented out at its default
+const DEFAULT_CONFIG: &str = r#"# timers_tui configuration
+#
+# Every setting below is commented out and shows its default. Lengths are
+# written like "90s", "3m", "1h30m" or a number of seconds. Tables come last,
+# since a key after a table header belongs to that table.
+#
+# Settings here go on top of /etc/timers_tui/config.toml, and can be changed
+# by a .timers_tui.toml in the current directory or one above it, by
+# environment variables like TIMERS_TUI_SNOOZE_LENGTH=10m or
+# TIMERS_TUI_HOOKS__ALARM=... and by --set snooze_length=10m. Run
+# `timers_tui config show` to see where each setting comes from. A
+# .timers_tui.toml can't set save_file, alarm_sound, calendar_file, hooks or
+# webhooks.
+#
+# Changes to config files are picked up while running, apart from save_file,
+# http_port, metrics_port, calendar_file and desktop_notifications which need
+# a restart.
+
+# Where timers are saved between runs, saved_timers.json in the timers_tui
+# data directory if not set (see `timers_tui paths`)
+# save_file = "/path/to/saved_timers.json"
+
+# Saving: timers are saved whenever they change and every autosave_interval
+# while some are running ("0" only saves on changes). The save file before
+# the last save is kept as saved_timers.json.1 and so on, up to backups files
+# autosave = true
+# autosave_interval = "30s"
+# backups = 3
+
+# How long (z) snoozes an alarm for
+# snooze_length = "5m"
+
+# The sound alarms play on repeat. If not set, rodio_alarm_test.flac in the
+# current directory, and a beep if that can't be played
+# alarm_sound = "/path/to/alarm.flac"
+
+# Notifications when a timer goes off: a desktop notification over D-Bus,
+# the terminal bell with an OSC 9/777 notification, and the countdown in the
+# terminal window title
+# desktop_notifications = true
+# terminal_notifications = true
+# window_title = true
+
+# A JSON API and server-sent events on 127.0.0.1
+# http_port = 7878
+
+# Prometheus metrics on 127.0.0.1, at /metrics
+# metrics_port = 9797
+
+# An iCalendar file with an event for when each running timer goes off
+# calendar_file = "/path/to/timers.ics"
+
+# Timers offered when adding a new one with (a)
+# [[presets]]
+# description = "Tea"
+# length = "3m"
+#
+# [[presets]]
+# description = "Pizza"
+# length = "12m"
+
+# Shell commands run on timer events, with the timer in TIMER_* environment
+# variables and the event as JSON on stdin. The events are start, pause,
+# alarm, acknowledge, snooze and delete
+# [hooks]
+# start = "notify-send started \"$TIMER_DESCRIPTION\""
+# alarm = "notify-send \"$TIMER_DESCRIPTION is done\""
+
+# POSTed the event as JSON when a timer with webhooks turned on (w) goes off
+# or is acknowledged
+# [webhooks]
+# urls = ["http://localhost:8080/timers"]
+# timeout = "5s"
+# retries = 3
+"#;
+
+// Every source of a profile's settings, checked one by one
+pub fn check_settings(
+    sources: &SettingsSources,
+    profile: &str,
+) -> Result<Vec<String>, SettingsError> {
+    load_settings(sources, profile)?;
+
+    Ok(settings_layers(sources, profile)?
+        .into_iter()
+        .skip(1)
+        .map(|layer| layer.source)
+        .collect())
+}
+
+pub fn write_default_config(path: &Path, force: bool) -> Result<(), String> {
+    if path.exists() && !force {
+        return Err(format!(
+            "{} already exists, pass --force to replace it",
+            path.display()
+        ));
+    }
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+
+    fs::write(path, DEFAULT_CONFIG).map_err(|e| e.to_string())
+}
+
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+// Notices when a config file is created, changed or removed
+pub struct SettingsWatcher {
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+impl SettingsWatcher {
+    pub fn new(files: Vec<PathBuf>) -> SettingsWatcher {
+        SettingsWatcher {
+            files: files
+                .into_iter()
+                .map(|path| {
+                    let modified = modified(&path);
+                    (path, modified)
+                })
+                .collect(),
+        }
+    }
+
+    // Whether any of the files changed since the last check
+    pub fn check(&mut self) -> bool {
+        let mut changed = false;
+
+        for (path, last_modified) in &mut self.files {
+            let modified = modified(path);
+
+            if modified != *last_modified {
+                *last_modified = modified;
+                changed = true;
+            }
+        }
+
+        changed
+    }
+
+    // Checks in the background, asking the event loop to reload the settings
+    // until the returned watcher is dropped
+    pub fn spawn(
+        files: Vec<PathBuf>,
+        command_sender: Sender<TimerCommand>,
+    ) -> Arc<Mutex<SettingsWatcher>> {
+        let watcher = Arc::new(Mutex::new(SettingsWatcher::new(files)));
+        let thread_watcher = Arc::clone(&watcher);
+
+        thread::spawn(move || loop {
+            thread::sleep(WATCH_INTERVAL);
+
+            if Arc::strong_count(&thread_watcher) == 1 {
+                return;
+            }
+
+            let changed = match thread_watcher.lock() {
+                Ok(mut watcher) => watcher.check(),
+                Err(_) => return,
+            };
+
+            if changed && command_sender.send(TimerCommand::ReloadSettings).is_err() {
+                return;
+            }
+        });
+
+        watcher
+    }
+}
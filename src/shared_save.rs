@@ -0,0 +1,197 @@
+use std::{
+    fs::{self, File, OpenOptions, TryLockError},
+    io::{self, BufRead, IsTerminal, Write},
+    path::{Path, PathBuf},
+    sync::{mpsc::Sender, Arc, Mutex},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use timers_core::{
+    persistence::{parse_saved_timers, wrote_last},
+    SerializeableTimerParts,
+};
+
+use crate::app::TimerCommand;
+
+// How often the save file is checked for changes from elsewhere
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum SaveMode {
+    // Holds the lock on the save file and saves to it
+    Owner,
+    // Never saves, only follows changes to the save file
+    ReadOnly,
+    // Saves alongside the instance holding the lock, merging its changes
+    Attach,
+}
+
+// An advisory lock on a file next to the save file, held for as long as the
+// instance runs. The save file itself can't be locked, since every save
+// renames a new file over it
+pub struct SaveFileLock {
+    _file: File,
+}
+
+pub fn lock_path(save_file: &Path) -> PathBuf {
+    let mut path = save_file.as_os_str().to_owned();
+    path.push(".lock");
+
+    PathBuf::from(path)
+}
+
+impl SaveFileLock {
+    // None when another instance already holds the lock
+    pub fn acquire(save_file: &Path) -> io::Result<Option<SaveFileLock>> {
+        if let Some(parent) = save_file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(lock_path(save_file))?;
+
+        match file.try_lock() {
+            Ok(()) => Ok(Some(SaveFileLock { _file: file })),
+            Err(TryLockError::WouldBlock) => Ok(None),
+            Err(TryLockError::Error(error)) => Err(error),
+        }
+    }
+}
+
+// Asks what to do when another instance holds the lock. Without a terminal
+// to ask on, it is read-only
+pub fn ask_save_mode(save_file: &Path) -> SaveMode {
+    let stdin = io::stdin();
+
+    if !stdin.is_terminal() {
+        return SaveMode::ReadOnly;
+    }
+
+    println!(
+        "Another timers_tui is already using {}",
+        save_file.display()
+    );
+    println!("  (r) Open it read-only, following the other instance's changes");
+    println!("  (a) Attach, saving alongside it and merging its changes");
+    println!("  (q) Quit");
+
+    loop {
+        print!("> ");
+        let _ = io::stdout().flush();
+
+        let mut answer = String::new();
+
+        if stdin.lock().read_line(&mut answer).unwrap_or(0) == 0 {
+            return SaveMode::ReadOnly;
+        }
+
+        match answer.trim() {
+            "r" | "R" => return SaveMode::ReadOnly,
+            "a" | "A" => return SaveMode::Attach,
+            "q" | "Q" => std::process::exit(0),
+            _ => (),
+        }
+    }
+}
+
+// Notices when something other than this instance changes the save file,
+// such as another instance, the CLI or a sync tool
+pub struct SaveFileWatcher {
+    save_file: PathBuf,
+    modified: Option<SystemTime>,
+    contents: Option<String>,
+    timers: Vec<SerializeableTimerParts>,
+}
+
+impl SaveFileWatcher {
+    pub fn new(save_file: PathBuf) -> SaveFileWatcher {
+        let modified = fs::metadata(&save_file)
+            .and_then(|meta| meta.modified())
+            .ok();
+        let contents = fs::read_to_string(&save_file).ok();
+        let timers = contents
+            .as_deref()
+            .and_then(|contents| parse_saved_timers(contents).ok())
+            .unwrap_or_default();
+
+        SaveFileWatcher {
+            save_file,
+            modified,
+            contents,
+            timers,
+        }
+    }
+
+    // The timers before and after a change someone else made since the last
+    // check, if there was one
+    pub fn check(
+        &mut self,
+    ) -> Option<(Vec<SerializeableTimerParts>, Vec<SerializeableTimerParts>)> {
+        let modified = fs::metadata(&self.save_file)
+            .and_then(|meta| meta.modified())
+            .ok()?;
+
+        if Some(modified) == self.modified {
+            return None;
+        }
+
+        let contents = fs::read_to_string(&self.save_file).ok()?;
+
+        if Some(&contents) == self.contents.as_ref() {
+            self.modified = Some(modified);
+            return None;
+        }
+
+        // Something is still halfway through writing it
+        let timers = parse_saved_timers(&contents).ok()?;
+
+        let before = std::mem::replace(&mut self.timers, timers.clone());
+        self.modified = Some(modified);
+        let own_write = wrote_last(&self.save_file, &contents);
+        self.contents = Some(contents);
+
+        if own_write {
+            None
+        } else {
+            Some((before, timers))
+        }
+    }
+
+    // Checks in the background, sending changes to the event loop
+    pub fn spawn(
+        save_file: PathBuf,
+        command_sender: Sender<TimerCommand>,
+    ) -> Arc<Mutex<SaveFileWatcher>> {
+        let watcher = Arc::new(Mutex::new(SaveFileWatcher::new(save_file)));
+        let thread_watcher = Arc::clone(&watcher);
+
+        thread::spawn(move || loop {
+            thread::sleep(WATCH_INTERVAL);
+
+            // The profile was closed
+            if Arc::strong_count(&thread_watcher) == 1 {
+                return;
+            }
+
+            let change = match thread_watcher.lock() {
+                Ok(mut watcher) => watcher.check(),
+                Err(_) => return,
+            };
+
+            if let Some((before, after)) = change {
+                if command_sender
+                    .send(TimerCommand::MergeExternal(before, after))
+                    .is_err()
+                {
+                    return;
+                }
+            }
+        });
+
+        watcher
+    }
+}
@@ -0,0 +1,47 @@
+use std::{io, sync::mpsc::Sender};
+
+#[cfg(unix)]
+use std::thread;
+
+#[cfg(unix)]
+use signal_hook::{
+    consts::{SIGHUP, SIGINT, SIGTERM, SIGUSR1, SIGUSR2},
+    iterator::Signals,
+};
+
+use crate::app::TimerCommand;
+
+// Turns signals into commands for the event loop:
+//
+//   SIGUSR1          pause every running timer
+//   SIGUSR2          silence every alarm
+//   SIGHUP           reload the config and merge in changes to the save file
+//   SIGTERM, SIGINT  quit, restoring the terminal and saving timers
+#[cfg(unix)]
+pub fn listen_for_signals(command_sender: Sender<TimerCommand>) -> io::Result<()> {
+    let mut signals = Signals::new([SIGUSR1, SIGUSR2, SIGHUP, SIGTERM, SIGINT])?;
+
+    thread::spawn(move || {
+        for signal in signals.forever() {
+            let command = match signal {
+                SIGUSR1 => TimerCommand::PauseAll,
+                SIGUSR2 => TimerCommand::AcknowledgeAll,
+                SIGHUP => TimerCommand::Reload,
+                _ => TimerCommand::Quit,
+            };
+
+            if command_sender.send(command).is_err() {
+                break;
+            }
+        }
+    });
+
+    Ok(())
+}
+
+// There are no such signals to listen for elsewhere, and Ctrl-C reaches the
+// TUI as a key press in raw mode
+#[cfg(not(unix))]
+pub fn listen_for_signals(_command_sender: Sender<TimerCommand>) -> io::Result<()> {
+    Ok(())
+}
@@ -0,0 +1,65 @@
+use std::{fs::File, io::BufReader, path::Path, time::Duration};
+
+use rodio::{source::SineWave, Decoder, OutputStream, OutputStreamHandle, Sink, Source};
+use timers_core::Alarm;
+
+// Played when no alarm_sound is set, with a beep if it isn't there either
+pub const DEFAULT_ALARM_SOUND: &str = "rodio_alarm_test.flac";
+
+// Loops the alarm sound on the default output device while timers alarm
+pub struct SoundAlarm {
+    // False when the sound file couldn't be played and it beeps instead
+    pub playing_file: bool,
+    _stream: OutputStream,
+    _stream_handle: OutputStreamHandle,
+    alarm_sink: Sink,
+}
+
+impl SoundAlarm {
+    // None when there is no sound device to play on
+    pub fn new(filename: &Path) -> Option<SoundAlarm> {
+        let (_stream, stream_handle) = OutputStream::try_default().ok()?;
+        let sink = Sink::try_new(&stream_handle).ok()?;
+
+        // A file that can't be opened or decoded beeps instead, the same way
+        let decoder = File::open(filename)
+            .ok()
+            .and_then(|file| Decoder::new(BufReader::new(file)).ok());
+
+        let playing_file = match decoder {
+            Some(decoder) => {
+                sink.append(decoder.repeat_infinite());
+                true
+            }
+            None => {
+                let source = SineWave::new(600.0)
+                    .take_duration(Duration::from_millis(1000))
+                    .delay(Duration::from_millis(1000))
+                    .repeat_infinite()
+                    .skip_duration(Duration::from_millis(1000));
+
+                sink.append(source);
+                false
+            }
+        };
+
+        sink.pause();
+
+        Some(SoundAlarm {
+            playing_file,
+            _stream,
+            _stream_handle: stream_handle,
+            alarm_sink: sink,
+        })
+    }
+}
+
+impl Alarm for SoundAlarm {
+    fn start(&mut self) {
+        self.alarm_sink.play();
+    }
+
+    fn stop(&mut self) {
+        self.alarm_sink.pause();
+    }
+}
@@ -0,0 +1,271 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process,
+    thread::sleep,
+    time::Duration,
+};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use timers_core::{
+    change_key,
+    persistence::{parse_saved_timers, unix_now, write_atomically},
+    TimerEvent, TimerEventListener, TimerSnapshot, TimerState,
+};
+
+use crate::{paths::runtime_dir, ui::format_duration};
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum StatusFormat {
+    /// One line for tmux status-right and friends
+    Plain,
+    /// A waybar custom module, use with "return-type": "json"
+    Waybar,
+    /// The i3bar protocol, also read by swaybar
+    I3bar,
+}
+
+// What a running TUI last saw of its timers, so the status command can show
+// live countdowns without talking to it
+#[derive(Serialize, Deserialize)]
+struct LiveState {
+    // The instance that wrote it, the only one that may remove it
+    pid: u32,
+    save_file: Option<PathBuf>,
+    written_at: f64,
+    timers: Vec<TimerSnapshot>,
+}
+
+// Every profile has its own, so instances on other profiles don't write over
+// each other
+pub fn live_state_path(profile: &str) -> Option<PathBuf> {
+    Some(runtime_dir()?.join(format!("live_state-{}.json", profile)))
+}
+
+fn read_live_state(path: &Path) -> Option<LiveState> {
+    let contents = fs::read_to_string(path).ok()?;
+
+    serde_json::from_str(&contents).ok()
+}
+
+// Keeps the live state file up to date while the TUI runs. Running timers
+// are written with the time they were written at, so the file only changes
+// when something other than the countdown does
+pub struct LiveStateWriter {
+    path: PathBuf,
+    save_file: Option<PathBuf>,
+    last_change_key: String,
+}
+
+impl LiveStateWriter {
+    pub fn new(profile: &str, save_file: Option<PathBuf>) -> Option<LiveStateWriter> {
+        let path = live_state_path(profile)?;
+
+        fs::create_dir_all(path.parent()?).ok()?;
+
+        Some(LiveStateWriter {
+            path,
+            save_file,
+            last_change_key: String::new(),
+        })
+    }
+
+    fn write(&self, timers: &[TimerSnapshot]) -> io::Result<()> {
+        let state = LiveState {
+            pid: process::id(),
+            save_file: self.save_file.clone(),
+            written_at: unix_now().as_secs_f64(),
+            timers: timers.to_vec(),
+        };
+
+        // Readers never see half of it
+        write_atomically(&self.path, &serde_json::to_string(&state)?)
+    }
+}
+
+impl TimerEventListener for LiveStateWriter {
+    fn on_event(&mut self, _event: &TimerEvent) {}
+
+    fn on_update(&mut self, timers: &[TimerSnapshot]) {
+        let key = change_key(timers);
+
+        if key != self.last_change_key && self.write(timers).is_ok() {
+            self.last_change_key = key;
+        }
+    }
+}
+
+impl Drop for LiveStateWriter {
+    fn drop(&mut self) {
+        // Nothing is running once the TUI is gone, unless another instance
+        // has taken the file over since
+        let ours = read_live_state(&self.path).is_some_and(|state| state.pid == process::id());
+
+        if ours {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+struct Status {
+    running: Vec<(String, Duration)>,
+    alarming: Vec<String>,
+    stopped: Vec<(String, Duration)>,
+}
+
+// From the live state of a TUI running the profile on the same save file, or
+// from the save file if there is none
+fn read_status(profile: &str, saved_file: &Option<PathBuf>) -> Status {
+    let live_state = live_state_path(profile)
+        .and_then(|path| read_live_state(&path))
+        .filter(|live_state| live_state.save_file == *saved_file);
+
+    let mut status = Status {
+        running: Vec::new(),
+        alarming: Vec::new(),
+        stopped: Vec::new(),
+    };
+
+    match live_state {
+        Some(live_state) => {
+            let since_written = Duration::from_secs_f64(
+                (unix_now().as_secs_f64() - live_state.written_at).max(0.0),
+            );
+
+            for timer in live_state.timers {
+                match timer.state {
+                    TimerState::Running => {
+                        match timer.parts.time_left.checked_sub(since_written) {
+                            Some(time_left) => {
+                                status.running.push((timer.parts.description, time_left))
+                            }
+                            // It has gone off since the TUI last wrote
+                            None => status.alarming.push(timer.parts.description),
+                        }
+                    }
+                    TimerState::Alarming => status.alarming.push(timer.parts.description),
+                    TimerState::Stopped => status
+                        .stopped
+                        .push((timer.parts.description, timer.parts.time_left)),
+                }
+            }
+        }
+        // Without a running TUI every timer is stopped where it was saved
+        None => {
+            let saved_timers = saved_file
+                .as_ref()
+                .and_then(|path| fs::read_to_string(path).ok())
+                .and_then(|contents| parse_saved_timers(&contents).ok())
+                .unwrap_or_default();
+
+            for timer in saved_timers {
+                status.stopped.push((timer.description, timer.time_left));
+            }
+        }
+    }
+
+    status.running.sort_by_key(|(_, time_left)| *time_left);
+
+    status
+}
+
+fn plain_text(status: &Status) -> String {
+    let mut parts = Vec::new();
+
+    if !status.alarming.is_empty() {
+        parts.push(format!("⏰ {}", status.alarming.join(", ")));
+    }
+
+    if let Some((description, time_left)) = status.running.first() {
+        parts.push(format!("⏱ {} {}", format_duration(*time_left), description));
+    }
+
+    parts.join(" ")
+}
+
+fn tooltip(status: &Status) -> String {
+    let mut lines: Vec<String> = status
+        .alarming
+        .iter()
+        .map(|description| format!("{} - alarming", description))
+        .collect();
+
+    for (description, time_left) in &status.running {
+        lines.push(format!("{} - {}", description, format_duration(*time_left)));
+    }
+
+    for (description, time_left) in &status.stopped {
+        lines.push(format!(
+            "{} - {} (stopped)",
+            description,
+            format_duration(*time_left)
+        ));
+    }
+
+    lines.join("\n")
+}
+
+fn class(status: &Status) -> &'static str {
+    if !status.alarming.is_empty() {
+        "alarming"
+    } else if !status.running.is_empty() {
+        "running"
+    } else {
+        "idle"
+    }
+}
+
+fn format_status(status: &Status, format: StatusFormat) -> String {
+    match format {
+        StatusFormat::Plain => plain_text(status),
+        StatusFormat::Waybar => json!({
+            "text": plain_text(status),
+            "tooltip": tooltip(status),
+            "class": class(status),
+            "alt": class(status),
+        })
+        .to_string(),
+        StatusFormat::I3bar => json!([{
+            "name": "timers_tui",
+            "full_text": plain_text(status),
+            "urgent": !status.alarming.is_empty(),
+        }])
+        .to_string(),
+    }
+}
+
+pub fn print_status(
+    profile: &str,
+    saved_file: &Option<PathBuf>,
+    format: StatusFormat,
+    watch: bool,
+    interval: Duration,
+) -> io::Result<()> {
+    let mut stdout = io::stdout();
+
+    if let StatusFormat::I3bar = format {
+        writeln!(stdout, "{}", json!({ "version": 1 }))?;
+        writeln!(stdout, "[")?;
+    }
+
+    loop {
+        let line = format_status(&read_status(profile, saved_file), format);
+
+        match format {
+            // The i3bar protocol is one endless JSON array
+            StatusFormat::I3bar if watch => writeln!(stdout, "{},", line)?,
+            _ => writeln!(stdout, "{}", line)?,
+        }
+        stdout.flush()?;
+
+        if !watch {
+            return Ok(());
+        }
+
+        sleep(interval);
+    }
+}
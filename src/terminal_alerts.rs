@@ -0,0 +1,161 @@
+use std::{
+    env,
+    io::{self, Write},
+    time::{Duration, Instant},
+};
+
+use crossterm::{execute, terminal::SetTitle};
+
+use timers_core::{TimerEvent, TimerEventKind, TimerEventListener, TimerSnapshot, TimerState};
+
+// How often the bell rings while something alarms and there is no sound
+const BELL_REPEAT: Duration = Duration::from_secs(2);
+
+// Alerts through the terminal itself: BEL, OSC 9 and OSC 777 desktop
+// notifications, and a window title showing the soonest timer
+pub struct TerminalAlerts {
+    notifications: bool,
+    window_title: bool,
+    // Keep ringing while alarming, for when there is no alarm sound
+    repeat_bell: bool,
+    last_bell: Option<Instant>,
+    last_title: String,
+}
+
+impl TerminalAlerts {
+    pub fn new(notifications: bool, window_title: bool, repeat_bell: bool) -> TerminalAlerts {
+        if window_title {
+            // Save the current title so it comes back when we exit
+            let _ = write_escape("\x1b[22;0t");
+        }
+
+        TerminalAlerts {
+            notifications,
+            window_title,
+            repeat_bell,
+            last_bell: None,
+            last_title: String::new(),
+        }
+    }
+
+    fn ring_bell(&mut self) {
+        let _ = write_escape("\x07");
+        self.last_bell = Some(Instant::now());
+    }
+}
+
+fn write_escape(escape: &str) -> io::Result<()> {
+    let mut stderr = io::stderr();
+    stderr.write_all(escape.as_bytes())?;
+    stderr.flush()
+}
+
+// tmux swallows escapes it doesn't know unless they are passed through
+fn passthrough(escape: String) -> String {
+    if env::var_os("TMUX").is_some() {
+        format!("\x1bPtmux;{}\x1b\\", escape.replace('\x1b', "\x1b\x1b"))
+    } else {
+        escape
+    }
+}
+
+fn notification_escapes(description: &str) -> String {
+    // Keep the text from ending the sequence early
+    let description: String = description
+        .chars()
+        .filter(|c| !c.is_control() && *c != ';')
+        .collect();
+
+    passthrough(format!("\x1b]9;Timer finished: {}\x07", description))
+        + &passthrough(format!(
+            "\x1b]777;notify;Timer finished;{}\x07",
+            description
+        ))
+}
+
+fn short_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+
+    if seconds >= 3600 {
+        format!(
+            "{}:{:02}:{:02}",
+            seconds / 3600,
+            seconds % 3600 / 60,
+            seconds % 60
+        )
+    } else {
+        format!("{:02}:{:02}", seconds / 60, seconds % 60)
+    }
+}
+
+fn window_title(timers: &[TimerSnapshot]) -> String {
+    let alarming: Vec<&str> = timers
+        .iter()
+        .filter(|timer| matches!(timer.state, TimerState::Alarming))
+        .map(|timer| timer.parts.description.as_str())
+        .collect();
+
+    if !alarming.is_empty() {
+        return format!("⏰ {}", alarming.join(", "));
+    }
+
+    let soonest = timers
+        .iter()
+        .filter(|timer| matches!(timer.state, TimerState::Running))
+        .min_by_key(|timer| timer.parts.time_left);
+
+    match soonest {
+        Some(timer) => format!(
+            "⏱ {} {}",
+            short_duration(timer.parts.time_left),
+            timer.parts.description
+        ),
+        None => String::from("Timers TUI"),
+    }
+}
+
+impl TimerEventListener for TerminalAlerts {
+    fn on_event(&mut self, event: &TimerEvent) {
+        if let TimerEventKind::Alarmed = event.kind {
+            // With terminal_notifications off the bell only stands in for a
+            // missing alarm sound
+            if self.notifications || self.repeat_bell {
+                self.ring_bell();
+            }
+
+            if self.notifications {
+                let _ = write_escape(&notification_escapes(&event.description));
+            }
+        }
+    }
+
+    fn on_update(&mut self, timers: &[TimerSnapshot]) {
+        let alarming = timers
+            .iter()
+            .any(|timer| matches!(timer.state, TimerState::Alarming));
+
+        if self.repeat_bell && alarming {
+            match self.last_bell {
+                Some(last_bell) if last_bell.elapsed() < BELL_REPEAT => (),
+                _ => self.ring_bell(),
+            }
+        }
+
+        if self.window_title {
+            let title = window_title(timers);
+
+            if title != self.last_title {
+                let _ = execute!(io::stderr(), SetTitle(&title));
+                self.last_title = title;
+            }
+        }
+    }
+}
+
+impl Drop for TerminalAlerts {
+    fn drop(&mut self) {
+        if self.window_title {
+            let _ = write_escape("\x1b[23;0t");
+        }
+    }
+}
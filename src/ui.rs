@@ -1,14 +1,23 @@
-use std::{collections::HashSet, error::Error};
+use std::{collections::HashSet, error::Error, time::Duration};
 
 use lazy_static::lazy_static;
 use ratatui::{
+    layout::Alignment,
     prelude::{Backend, Constraint, Direction, Layout},
-    style::{Modifier, Style, Stylize},
-    widgets::{Block, BorderType, Borders, HighlightSpacing, Paragraph, Row, Table, TableState},
+    style::{Color, Modifier, Style, Stylize},
+    text::{Line, Span},
+    widgets::{
+        Block, BorderType, Borders, Clear, HighlightSpacing, Paragraph, Row, Table, TableState,
+        Wrap,
+    },
     Terminal,
 };
 
-use crate::app::{App, AppScreen, EditField};
+use crate::{
+    app::{App, AppScreen, EditField},
+    logging::recent_records,
+    profiles::DEFAULT_PROFILE,
+};
 
 // const HOURS_SET: HashSet<EditField> =
 // const MINUTES_SET: HashSet<EditField> = HashSet::from([EditField::Minutes1, EditField::Minutes2]);
@@ -31,6 +40,15 @@ fn build_block(title: String, highlighted: bool) -> Block<'static> {
     }
 }
 
+pub fn format_duration(duration: Duration) -> String {
+    format!(
+        "{}:{:02}:{:02}",
+        duration.as_secs() / 3600,
+        duration.as_secs() % 3600 / 60,
+        duration.as_secs() % 60,
+    )
+}
+
 fn build_paragraph(value: String, highlighted: bool) -> Paragraph<'static> {
     if highlighted {
         Paragraph::new(value).style(Style::default()).red()
@@ -45,7 +63,7 @@ pub fn run_app<B: Backend>(
 ) -> Result<(), Box<dyn Error>> {
     let mut state = TableState::default().with_selected(app.selected_timer);
 
-    while !app.should_quit {
+    while !app.should_quit && !app.should_reload && !app.reload_settings {
         terminal.draw(|f| {
             let chunks = Layout::default()
                 .direction(Direction::Vertical)
@@ -56,44 +74,60 @@ pub fn run_app<B: Backend>(
                 ])
                 .split(f.area());
 
-            let title_block = Block::default().title("Timers").borders(Borders::NONE);
+            match &app.screen {
+                AppScreen::Searching => {
+                    let search_paragraph = Paragraph::new(format!("/{}", app.search_query));
+
+                    f.render_widget(search_paragraph, chunks[0]);
+                }
+                _ if !app.search_query.is_empty() => {
+                    let search_paragraph =
+                        Paragraph::new(format!("Filter: {}", app.search_query));
+
+                    f.render_widget(search_paragraph, chunks[0]);
+                }
+                _ => {
+                    let title_block = Block::default().title("Timers").borders(Borders::NONE);
+
+                    f.render_widget(title_block, chunks[0]);
+                }
+            }
+
+            if let Some(toast) = app.current_toast() {
+                let toast_paragraph = Paragraph::new(toast).alignment(Alignment::Right).bold();
 
-            f.render_widget(title_block, chunks[0]);
+                f.render_widget(toast_paragraph, chunks[0]);
+            }
 
             let mut timer_block_rows = Vec::<Row>::new();
 
-            for (i, timer) in app.timers.iter().enumerate() {
+            for i in app.visible_timers() {
+                let timer = &app.engine.timers()[i];
                 let mut timer_row = Vec::new();
 
                 timer_row.push(format!("{:4}", i));
                 timer_row.push(timer.clone_description());
 
-                timer_row.push(format!(
-                    "{}:{:02}:{:02}",
-                    timer.get_length().as_secs() / 3600,
-                    timer.get_length().as_secs() % 3600 / 60,
-                    timer.get_length().as_secs() % 60,
-                ));
-
-                timer_row.push(format!(
-                    "{}:{:02}:{:02}",
-                    timer.get_time_left().as_secs() / 3600,
-                    timer.get_time_left().as_secs() % 3600 / 60,
-                    timer.get_time_left().as_secs() % 60,
-                ));
-
-                let state_strslice = match timer.state {
-                    crate::app::TimerState::Stopped => "Stopped",
-                    crate::app::TimerState::Running => "Running",
-                    crate::app::TimerState::Alarming => "Alarming",
+                timer_row.push(format_duration(timer.get_length()));
+
+                timer_row.push(format_duration(timer.get_time_left()));
+
+                let state_strslice = match timer.state() {
+                    timers_core::TimerState::Stopped => "Stopped",
+                    timers_core::TimerState::Running => "Running",
+                    timers_core::TimerState::Alarming => "Alarming",
                 };
                 timer_row.push(state_strslice.to_string());
+
+                if timer.has_webhooks() {
+                    timer_row.push(String::from("On"));
+                }
                 // timer_row.push(timer_box.timer.running);
                 //
                 timer_block_rows.push(Row::new(timer_row));
             }
 
-            state.select(app.selected_timer);
+            state.select(app.selected_timer.map(|i| app.visible_position(i)));
 
             // let mut state = TableState::default().with_selected(app.selected_timer);
             // let mut state = TableState::default().with_selected(Some(0));
@@ -101,9 +135,10 @@ pub fn run_app<B: Backend>(
             let widths = [
                     Constraint::Percentage(10),
                     Constraint::Percentage(25),
-                    Constraint::Percentage(25),
-                    Constraint::Percentage(25),
+                    Constraint::Percentage(20),
+                    Constraint::Percentage(20),
                     Constraint::Percentage(15),
+                    Constraint::Percentage(10),
             ];
 
             let timer_block_table = Table::new(timer_block_rows, widths)
@@ -113,8 +148,16 @@ pub fn run_app<B: Backend>(
                     "Timer Length",
                     "Time Left",
                     "Status",
+                    "Webhooks",
                 ]))
-                .block(Block::default().title("Timers").borders(Borders::ALL))
+                .block(
+                    Block::default()
+                        .title(match app.profile.as_str() {
+                            DEFAULT_PROFILE => format!("Timers (sorted by {})", app.sort_mode.name()),
+                            profile => format!("Timers - {} (sorted by {})", profile, app.sort_mode.name()),
+                        })
+                        .borders(Borders::ALL),
+                )
                 .row_highlight_style(
                     Style::default()
                         .add_modifier(Modifier::BOLD)
@@ -147,13 +190,19 @@ pub fn run_app<B: Backend>(
             // let commands_paragraph = Paragraph::new("No help text available").block(commands_block);
 
             let commands_paragraph = match &app.screen {
-                AppScreen::Main => Paragraph::new("(q) - Quit | (j) - Select Next Timer | (k) Select Previous Timer | (a) - Add Timer | (d) - Delete Timer | (p) - Toggle Timer | (r) - Reset Timer | (e) - Edit Timer").block(commands_block),
+                AppScreen::Main => Paragraph::new("(q) - Quit | (j) - Select Next Timer | (k) Select Previous Timer | (a) - Add Timer | (d) - Delete Timer | (p) - Toggle Timer | (r) - Reset Timer | (z) - Snooze Alarm | (w) - Toggle Webhooks | (e) - Edit Timer | (J/K) - Move Timer Down/Up | (s) - Change Sort | (P) - Switch Profile | (x) - Export | (L) - Log | (/) - Search | (n/N) - Next/Previous Match | (Esc) - Clear Search").block(commands_block),
                 AppScreen::Editing(edit_field) => {
                     match edit_field {
                         EditField::Description => Paragraph::new("(Tab) - Switch Field | (Enter) - Accept").block(commands_block),
                         _ => Paragraph::new("(Tab) - Switch Field | (Enter) - Accept | (j) - Decrement | (k) - Increment | (0-9) - Set Value").block(commands_block),
                     }
                 },
+                AppScreen::Export => Paragraph::new("(c) - Export As CSV | (t) - Export As TOML | (y) - Export As YAML | (Esc) - Cancel").block(commands_block),
+                AppScreen::Profiles => Paragraph::new("(j) - Select Next Profile | (k) - Select Previous Profile | (Enter) - Switch To Selected | (Esc) - Cancel").block(commands_block),
+                AppScreen::Log => Paragraph::new("(k) - Older | (j) - Newer | (g) - Oldest | (G) - Newest | (Esc) - Close").block(commands_block),
+                AppScreen::Presets => Paragraph::new("(1-9) - Create From Preset | (j) - Select Next Preset | (k) - Select Previous Preset | (Enter) - Create From Selected | (c) - Custom Timer | (Esc) - Cancel").block(commands_block),
+                AppScreen::Searching => Paragraph::new("(Enter) - Keep Filter | (Esc) - Clear Filter | (Backspace) - Delete Character").block(commands_block),
+                AppScreen::LengthChange => Paragraph::new("(e) - Keep Elapsed Time | (r) - Keep Remaining Time | (s) - Restart | (Esc) - Keep Old Length").block(commands_block),
                 AppScreen::Navigating => todo!(),
                 AppScreen::Error(_) => Paragraph::new("(q) - Quit").block(commands_block),
             };
@@ -294,6 +343,195 @@ pub fn run_app<B: Backend>(
                     // f.render_widget(minutes_input, editing_layout[2]);
                     // f.render_widget(seconds_input, editing_layout[3]);
                 }
+                AppScreen::Presets => {
+                    let presets_layout = Layout::default()
+                        .direction(Direction::Vertical)
+                        .constraints([
+                            Constraint::Percentage(25),
+                            Constraint::Percentage(50),
+                            Constraint::Percentage(25),
+                        ])
+                        .split(f.area());
+
+                    let presets_layout = Layout::default()
+                        .direction(Direction::Horizontal)
+                        .constraints([
+                            Constraint::Percentage(30),
+                            Constraint::Percentage(40),
+                            Constraint::Percentage(30),
+                        ])
+                        .split(presets_layout[1]);
+
+                    let mut preset_rows = Vec::<Row>::new();
+
+                    for (i, preset) in app.presets.iter().enumerate() {
+                        let number = if i < 9 {
+                            format!("({})", i + 1)
+                        } else {
+                            String::new()
+                        };
+
+                        preset_rows.push(Row::new(vec![
+                            number,
+                            preset.description.clone(),
+                            format_duration(preset.length),
+                        ]));
+                    }
+
+                    let widths = [
+                        Constraint::Percentage(15),
+                        Constraint::Percentage(55),
+                        Constraint::Percentage(30),
+                    ];
+
+                    let presets_table = Table::new(preset_rows, widths)
+                        .block(Block::default().borders(Borders::ALL).title("New Timer From Preset"))
+                        .row_highlight_style(
+                            Style::default()
+                                .add_modifier(Modifier::BOLD)
+                                .add_modifier(Modifier::UNDERLINED),
+                        )
+                        .highlight_symbol(">>")
+                        .highlight_spacing(HighlightSpacing::Always);
+
+                    let mut presets_state =
+                        TableState::default().with_selected(Some(app.selected_preset));
+
+                    f.render_widget(Clear, presets_layout[1]);
+                    f.render_stateful_widget(presets_table, presets_layout[1], &mut presets_state);
+                }
+                AppScreen::Profiles => {
+                    let profiles_layout = Layout::default()
+                        .direction(Direction::Vertical)
+                        .constraints([
+                            Constraint::Percentage(25),
+                            Constraint::Percentage(50),
+                            Constraint::Percentage(25),
+                        ])
+                        .split(f.area());
+
+                    let profiles_layout = Layout::default()
+                        .direction(Direction::Horizontal)
+                        .constraints([
+                            Constraint::Percentage(30),
+                            Constraint::Percentage(40),
+                            Constraint::Percentage(30),
+                        ])
+                        .split(profiles_layout[1]);
+
+                    let profile_rows: Vec<Row> = app
+                        .profiles
+                        .iter()
+                        .map(|profile| {
+                            let current = if *profile == app.profile { "(current)" } else { "" };
+
+                            Row::new(vec![profile.clone(), String::from(current)])
+                        })
+                        .collect();
+
+                    let widths = [Constraint::Percentage(70), Constraint::Percentage(30)];
+
+                    let profiles_table = Table::new(profile_rows, widths)
+                        .block(Block::default().borders(Borders::ALL).title("Switch Profile"))
+                        .row_highlight_style(
+                            Style::default()
+                                .add_modifier(Modifier::BOLD)
+                                .add_modifier(Modifier::UNDERLINED),
+                        )
+                        .highlight_symbol(">>")
+                        .highlight_spacing(HighlightSpacing::Always);
+
+                    let mut profiles_state =
+                        TableState::default().with_selected(Some(app.selected_profile));
+
+                    f.render_widget(Clear, profiles_layout[1]);
+                    f.render_stateful_widget(profiles_table, profiles_layout[1], &mut profiles_state);
+                }
+                AppScreen::Log => {
+                    let log_layout = Layout::default()
+                        .direction(Direction::Vertical)
+                        .constraints([
+                            Constraint::Percentage(10),
+                            Constraint::Percentage(75),
+                            Constraint::Percentage(15),
+                        ])
+                        .split(f.area());
+
+                    let log_layout = Layout::default()
+                        .direction(Direction::Horizontal)
+                        .constraints([
+                            Constraint::Percentage(5),
+                            Constraint::Percentage(90),
+                            Constraint::Percentage(5),
+                        ])
+                        .split(log_layout[1]);
+
+                    let records = recent_records();
+
+                    // The newest record at the bottom, unless scrolled back
+                    let height = log_layout[1].height.saturating_sub(2) as usize;
+                    let end = records.len().saturating_sub(app.log_scroll);
+                    let start = end.saturating_sub(height);
+
+                    let lines: Vec<Line> = records[start..end]
+                        .iter()
+                        .map(|record| {
+                            let level_style = match record.level.as_str() {
+                                "ERROR" => Style::default().fg(Color::Red),
+                                "WARN" => Style::default().fg(Color::Yellow),
+                                "INFO" => Style::default(),
+                                _ => Style::default().add_modifier(Modifier::DIM),
+                            };
+
+                            // Just the time of day from the UTC timestamp
+                            let time = record.time.get(11..19).unwrap_or(&record.time);
+
+                            Line::from(vec![
+                                Span::raw(format!("{} ", time)),
+                                Span::styled(format!("{:5} ", record.level), level_style),
+                                Span::raw(&record.message),
+                            ])
+                        })
+                        .collect();
+
+                    let title = match app.log_scroll {
+                        0 => String::from("Log (UTC)"),
+                        scroll => format!("Log (UTC, scrolled back {})", scroll),
+                    };
+
+                    let log_paragraph = Paragraph::new(lines)
+                        .block(Block::default().borders(Borders::ALL).title(title));
+
+                    f.render_widget(Clear, log_layout[1]);
+                    f.render_widget(log_paragraph, log_layout[1]);
+                }
+                AppScreen::LengthChange => {
+                    let length_layout = Layout::default()
+                        .direction(Direction::Vertical)
+                        .constraints([
+                            Constraint::Percentage(40),
+                            Constraint::Percentage(20),
+                            Constraint::Percentage(40),
+                        ])
+                        .split(f.area());
+
+                    let length_layout = Layout::default()
+                        .direction(Direction::Horizontal)
+                        .constraints([
+                            Constraint::Percentage(30),
+                            Constraint::Percentage(40),
+                            Constraint::Percentage(30),
+                        ])
+                        .split(length_layout[1]);
+
+                    let length_paragraph = Paragraph::new("The timer length changed. (e) Keep the time already elapsed (r) Keep the time remaining (s) Restart with the new length (Esc) Keep the old length")
+                        .block(Block::default().borders(Borders::ALL).title("Timer Length Changed"))
+                        .wrap(Wrap { trim: true })
+                        .style(Style::default());
+
+                    f.render_widget(Clear, length_layout[1]);
+                    f.render_widget(length_paragraph, length_layout[1]);
+                }
                 AppScreen::Error(error_type) => {
                     let error_layout = Layout::default()
                         .direction(Direction::Vertical)
@@ -314,13 +552,30 @@ pub fn run_app<B: Backend>(
                         .split(error_layout[1]);
 
                     let error_text = match error_type {
-                        crate::app::ErrorType::SoundDevice => String::from("Could not open sound device. You will not hear any sound when the alarm goes off (q) Quit (Enter) Continue Anyway"),
-                        crate::app::ErrorType::File => String::from("Could not open alarm sound file. Trying to use backup alarm sound (q) Quit (Enter) Continue Anyway"),
+                        crate::app::ErrorType::SoundDevice => String::from("Could not open sound device. Alarms will ring the terminal bell instead (q) Quit (Enter) Continue Anyway"),
+                        crate::app::ErrorType::File => String::from("Could not play alarm sound file. Trying to use backup alarm sound (q) Quit (Enter) Continue Anyway"),
+                        crate::app::ErrorType::BrokenSaveFile { reason, kept_aside, restored } => {
+                            let kept_aside = match kept_aside {
+                                Some(path) => format!("It was moved to {}.", path.display()),
+                                None => String::from("It could not be moved aside, so timers will not be saved."),
+                            };
+                            let restored = match restored {
+                                Some(path) => format!("Restored the timers from {}.", path.display()),
+                                None => String::from("No backup could be restored."),
+                            };
+
+                            format!("Could not load the saved timers: {}. {} {} (q) Quit (Enter) Continue", reason, kept_aside, restored)
+                        }
+                        crate::app::ErrorType::ExportFailed(error) => format!("Could not export the timers: {} (q) Quit (Enter) Continue", error),
+                        crate::app::ErrorType::ProfileInUse(profile) => format!("Another instance is using the profile '{}', so it was opened read-only (q) Quit (Enter) Continue", profile),
+                        crate::app::ErrorType::Settings(error) => format!("The config file was not applied: {} (q) Quit (Enter) Continue", error),
+                        crate::app::ErrorType::EditedTimerDeleted => String::from("The timer was deleted while it was being edited, so the changes were dropped (q) Quit (Enter) Continue"),
                     };
 
                     let error_paragraph = Paragraph::new(error_text)
                         .block(Block::default().borders(Borders::ALL))
-                        .style(Style::default());
+                        .style(Style::default())
+                        .wrap(Wrap { trim: true });
 
                     f.render_widget(error_paragraph, error_layout[1]);
                 }
@@ -330,7 +585,10 @@ pub fn run_app<B: Backend>(
 
         app.handle_events()?;
 
-        app.update_timers();
+        app.process_commands();
+        app.show_held_error();
+
+        app.engine.update_timers();
     }
     Ok(())
 }
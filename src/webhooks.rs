@@ -0,0 +1,273 @@
+use std::{thread, time::Duration};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+use ureq::{Agent, AgentBuilder};
+
+use timers_core::{TimerEvent, TimerEventKind, TimerEventListener};
+
+use crate::settings::Length;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+const DEFAULT_RETRIES: u32 = 3;
+const FIRST_BACKOFF: Duration = Duration::from_secs(1);
+
+// The [webhooks] table of the config file
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct WebhookSettings {
+    #[serde(default)]
+    urls: Vec<String>,
+    timeout: Option<Length>,
+    retries: Option<u32>,
+}
+
+// POSTs the event as JSON to every configured URL when a timer that opted in
+// starts alarming or is acknowledged. Every delivery runs on its own thread,
+// so a slow endpoint never holds up the event loop or the other URLs
+pub struct Webhooks {
+    urls: Vec<String>,
+    retries: u32,
+    // Doubled after every failed attempt
+    first_backoff: Duration,
+    agent: Agent,
+}
+
+impl Webhooks {
+    pub fn new(settings: &Option<WebhookSettings>) -> Option<Webhooks> {
+        let settings = settings.as_ref()?;
+
+        if settings.urls.is_empty() {
+            return None;
+        }
+
+        let timeout = match settings.timeout {
+            Some(Length(timeout)) => timeout,
+            None => DEFAULT_TIMEOUT,
+        };
+
+        Some(Webhooks {
+            urls: settings.urls.clone(),
+            retries: settings.retries.unwrap_or(DEFAULT_RETRIES),
+            first_backoff: FIRST_BACKOFF,
+            agent: AgentBuilder::new().timeout(timeout).build(),
+        })
+    }
+}
+
+fn deliver(agent: &Agent, url: &str, body: &str, retries: u32, first_backoff: Duration) {
+    let mut backoff = first_backoff;
+
+    for attempt in 0..=retries {
+        let result = agent
+            .post(url)
+            .set("Content-Type", "application/json")
+            .send_string(body);
+
+        match result {
+            Ok(_) => return,
+            // The endpoint understood and refused, so trying again won't help
+            Err(ureq::Error::Status(code, _)) if (400..500).contains(&code) => {
+                warn!("The webhook {} refused the event with {}", url, code);
+                return;
+            }
+            Err(error) => {
+                if attempt < retries {
+                    thread::sleep(backoff);
+                    backoff *= 2;
+                } else {
+                    warn!("Could not deliver the webhook {}: {}", url, error);
+                }
+            }
+        }
+    }
+}
+
+impl TimerEventListener for Webhooks {
+    fn on_event(&mut self, event: &TimerEvent) {
+        if !event.webhooks {
+            return;
+        }
+
+        match event.kind {
+            TimerEventKind::Alarmed | TimerEventKind::Acknowledged => (),
+            _ => return,
+        }
+
+        let Ok(body) = serde_json::to_string(event) else {
+            return;
+        };
+
+        for url in &self.urls {
+            let agent = self.agent.clone();
+            let url = url.clone();
+            let body = body.clone();
+            let retries = self.retries;
+            let first_backoff = self.first_backoff;
+
+            thread::spawn(move || deliver(&agent, &url, &body, retries, first_backoff));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        time::Instant,
+    };
+
+    use tiny_http::{Response, Server};
+
+    use super::*;
+
+    // A local endpoint that answers with the given statuses in turn, then
+    // 200, and keeps every request it got
+    struct Stub {
+        url: String,
+        requests: Arc<Mutex<Vec<(Instant, String)>>>,
+    }
+
+    impl Stub {
+        fn start(statuses: Vec<u16>) -> Stub {
+            let server = Server::http("127.0.0.1:0").unwrap();
+            let url = format!("http://{}/hook", server.server_addr());
+
+            let requests = Arc::new(Mutex::new(Vec::new()));
+            let stub_requests = Arc::clone(&requests);
+
+            thread::spawn(move || {
+                let mut statuses = statuses.into_iter();
+
+                for mut request in server.incoming_requests() {
+                    let mut body = String::new();
+                    let _ = request.as_reader().read_to_string(&mut body);
+
+                    stub_requests.lock().unwrap().push((Instant::now(), body));
+
+                    let status = statuses.next().unwrap_or(200);
+                    let _ = request.respond(Response::empty(status));
+                }
+            });
+
+            Stub { url, requests }
+        }
+
+        fn wait_for_requests(&self, count: usize) -> Vec<(Instant, String)> {
+            let started = Instant::now();
+
+            while started.elapsed() < Duration::from_secs(5) {
+                if self.requests.lock().unwrap().len() >= count {
+                    break;
+                }
+
+                thread::sleep(Duration::from_millis(10));
+            }
+
+            self.requests.lock().unwrap().clone()
+        }
+    }
+
+    fn webhooks(url: &str, retries: u32) -> Webhooks {
+        Webhooks {
+            urls: vec![String::from(url)],
+            retries,
+            first_backoff: Duration::from_millis(50),
+            agent: AgentBuilder::new().timeout(Duration::from_secs(2)).build(),
+        }
+    }
+
+    fn event(kind: TimerEventKind, webhooks: bool) -> TimerEvent {
+        TimerEvent {
+            kind,
+            id: 3,
+            index: 0,
+            description: String::from("Tea"),
+            length: Duration::from_secs(180),
+            time_left: Duration::ZERO,
+            overtime: Duration::from_secs(2),
+            webhooks,
+        }
+    }
+
+    #[test]
+    fn posts_the_event_as_json() {
+        let stub = Stub::start(Vec::new());
+
+        webhooks(&stub.url, 0).on_event(&event(TimerEventKind::Alarmed, true));
+
+        let requests = stub.wait_for_requests(1);
+        let body: serde_json::Value = serde_json::from_str(&requests[0].1).unwrap();
+
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "event": "alarmed",
+                "id": 3,
+                "index": 0,
+                "description": "Tea",
+                "length": 180,
+                "time_left": 0,
+                "overtime": 2,
+            })
+        );
+    }
+
+    #[test]
+    fn only_posts_alarms_and_acknowledgements_of_opted_in_timers() {
+        let stub = Stub::start(Vec::new());
+        let mut webhooks = webhooks(&stub.url, 0);
+
+        webhooks.on_event(&event(TimerEventKind::Alarmed, false));
+        webhooks.on_event(&event(TimerEventKind::Started, true));
+        webhooks.on_event(&event(TimerEventKind::Acknowledged, true));
+
+        // Give anything that shouldn't have been sent time to arrive
+        stub.wait_for_requests(1);
+        thread::sleep(Duration::from_millis(200));
+        let requests = stub.wait_for_requests(1);
+
+        assert_eq!(requests.len(), 1);
+        assert!(requests[0].1.contains("\"acknowledged\""));
+    }
+
+    #[test]
+    fn retries_server_errors_with_doubling_backoff() {
+        let stub = Stub::start(vec![500, 502, 503, 500]);
+
+        deliver(
+            &AgentBuilder::new().build(),
+            &stub.url,
+            "{}",
+            3,
+            Duration::from_millis(50),
+        );
+
+        let requests = stub.wait_for_requests(4);
+        assert_eq!(requests.len(), 4);
+
+        let gaps: Vec<Duration> = requests
+            .windows(2)
+            .map(|pair| pair[1].0 - pair[0].0)
+            .collect();
+
+        for (gap, backoff) in gaps.iter().zip([50, 100, 200]) {
+            assert!(*gap >= Duration::from_millis(backoff), "{:?}", gaps);
+        }
+    }
+
+    #[test]
+    fn gives_up_at_once_when_refused() {
+        let stub = Stub::start(vec![404]);
+
+        deliver(
+            &AgentBuilder::new().build(),
+            &stub.url,
+            "{}",
+            3,
+            Duration::from_millis(50),
+        );
+
+        assert_eq!(stub.wait_for_requests(1).len(), 1);
+    }
+}
@@ -0,0 +1,165 @@
+// What rings while any timer is alarming. It is started when the first timer
+// goes off and stopped once the last one is acknowledged, snoozed or deleted
+pub trait Alarm {
+    fn start(&mut self);
+
+    fn stop(&mut self);
+}
+
+// For front ends that show alarms some other way, or have nothing to ring
+pub struct SilentAlarm;
+
+impl Alarm for SilentAlarm {
+    fn start(&mut self) {}
+
+    fn stop(&mut self) {}
+}
+
+// Keeps the alarm going for as long as any timer is alarming
+pub(crate) struct AlarmCounter {
+    alarming_timers: u32,
+    alarm: Box<dyn Alarm>,
+}
+
+impl AlarmCounter {
+    pub(crate) fn new() -> AlarmCounter {
+        AlarmCounter {
+            alarming_timers: 0,
+            alarm: Box::new(SilentAlarm),
+        }
+    }
+
+    pub(crate) fn alarming_timers(&self) -> u32 {
+        self.alarming_timers
+    }
+
+    // The old alarm is dropped first, in case both need the same device.
+    // Timers that are alarming keep ringing with the new one
+    pub(crate) fn set_alarm(&mut self, mut alarm: Box<dyn Alarm>) {
+        if self.alarming_timers > 0 {
+            self.alarm.stop();
+        }
+
+        self.alarm = Box::new(SilentAlarm);
+
+        if self.alarming_timers > 0 {
+            alarm.start();
+        }
+
+        self.alarm = alarm;
+    }
+
+    pub(crate) fn increase_counter(&mut self) {
+        if self.alarming_timers == 0 {
+            self.alarm.start();
+        }
+
+        self.alarming_timers += 1;
+    }
+
+    // Never goes below zero, so a timer taken out of the count twice can't
+    // stop an alarm other timers still need
+    pub(crate) fn decrease_counter(&mut self) {
+        if self.alarming_timers == 1 {
+            self.alarm.stop();
+        }
+
+        self.alarming_timers = self.alarming_timers.saturating_sub(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+
+    // Writes down every start and stop, tagged with its own name
+    struct RecordingAlarm {
+        name: &'static str,
+        calls: Rc<RefCell<Vec<String>>>,
+    }
+
+    impl Alarm for RecordingAlarm {
+        fn start(&mut self) {
+            self.calls.borrow_mut().push(format!("{} start", self.name));
+        }
+
+        fn stop(&mut self) {
+            self.calls.borrow_mut().push(format!("{} stop", self.name));
+        }
+    }
+
+    fn counter_with_alarm(calls: &Rc<RefCell<Vec<String>>>) -> AlarmCounter {
+        let mut counter = AlarmCounter::new();
+        counter.set_alarm(Box::new(RecordingAlarm {
+            name: "first",
+            calls: Rc::clone(calls),
+        }));
+
+        counter
+    }
+
+    #[test]
+    fn rings_from_the_first_alarming_timer_to_the_last() {
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let mut counter = counter_with_alarm(&calls);
+
+        counter.increase_counter();
+        counter.increase_counter();
+        counter.decrease_counter();
+
+        assert_eq!(counter.alarming_timers(), 1);
+        assert_eq!(*calls.borrow(), ["first start"]);
+
+        counter.decrease_counter();
+
+        assert_eq!(counter.alarming_timers(), 0);
+        assert_eq!(*calls.borrow(), ["first start", "first stop"]);
+    }
+
+    #[test]
+    fn does_not_count_below_zero() {
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let mut counter = counter_with_alarm(&calls);
+
+        counter.decrease_counter();
+        assert_eq!(counter.alarming_timers(), 0);
+
+        counter.increase_counter();
+
+        assert_eq!(counter.alarming_timers(), 1);
+        assert_eq!(*calls.borrow(), ["first start"]);
+    }
+
+    #[test]
+    fn a_new_alarm_takes_over_ringing() {
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let mut counter = counter_with_alarm(&calls);
+
+        counter.increase_counter();
+        counter.set_alarm(Box::new(RecordingAlarm {
+            name: "second",
+            calls: Rc::clone(&calls),
+        }));
+        counter.decrease_counter();
+
+        assert_eq!(
+            *calls.borrow(),
+            ["first start", "first stop", "second start", "second stop"]
+        );
+    }
+
+    #[test]
+    fn a_new_alarm_stays_quiet_with_nothing_alarming() {
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let mut counter = counter_with_alarm(&calls);
+
+        counter.set_alarm(Box::new(RecordingAlarm {
+            name: "second",
+            calls: Rc::clone(&calls),
+        }));
+
+        assert!(calls.borrow().is_empty());
+    }
+}
@@ -0,0 +1,693 @@
+use std::time::{Duration, Instant};
+
+use crate::{
+    alarm::{Alarm, AlarmCounter},
+    events::{TimerEvent, TimerEventKind, TimerEventListener},
+    persistence::{parse_saved_timers, saved_timers_json, LoadError},
+    timer::{LengthChange, SerializeableTimerParts, Timer, TimerSnapshot, TimerState},
+};
+
+// Owns the timers and everything that happens to them. It has no clock of
+// its own, so whatever embeds it calls update_timers regularly, and every
+// change goes out to the listeners
+pub struct TimerEngine {
+    timers: Vec<Timer>,
+    pub snooze_length: Duration,
+    alarm_counter: AlarmCounter,
+    next_timer_id: u64,
+    // Listeners are added in named groups, so one set of them, like the ones
+    // built from settings, can be replaced without touching the rest
+    listeners: Vec<(&'static str, Box<dyn TimerEventListener>)>,
+}
+
+impl Default for TimerEngine {
+    fn default() -> TimerEngine {
+        TimerEngine::new()
+    }
+}
+
+impl TimerEngine {
+    pub fn new() -> TimerEngine {
+        TimerEngine {
+            timers: Vec::new(),
+            snooze_length: Duration::from_secs(300),
+            alarm_counter: AlarmCounter::new(),
+            next_timer_id: 0,
+            listeners: Vec::new(),
+        }
+    }
+
+    pub fn timers(&self) -> &[Timer] {
+        &self.timers
+    }
+
+    pub fn snapshots(&self) -> Vec<TimerSnapshot> {
+        self.timers.iter().map(|timer| timer.snapshot()).collect()
+    }
+
+    pub fn set_alarm(&mut self, alarm: Box<dyn Alarm>) {
+        self.alarm_counter.set_alarm(alarm);
+    }
+
+    pub fn alarming_timers(&self) -> u32 {
+        self.alarm_counter.alarming_timers()
+    }
+
+    pub fn add_listener(&mut self, group: &'static str, listener: Box<dyn TimerEventListener>) {
+        self.listeners.push((group, listener));
+    }
+
+    pub fn remove_listeners(&mut self, group: &str) {
+        self.listeners
+            .retain(|(listener_group, _)| *listener_group != group);
+    }
+
+    fn emit(&mut self, kind: TimerEventKind, i: usize) {
+        let timer = &self.timers[i];
+
+        let event = TimerEvent {
+            kind,
+            id: timer.id,
+            index: i,
+            description: timer.clone_description(),
+            length: timer.get_length(),
+            time_left: timer.get_time_left(),
+            overtime: timer.get_overtime(),
+            webhooks: timer.serializeable_parts.webhooks,
+        };
+
+        for (_, listener) in self.listeners.iter_mut() {
+            listener.on_event(&event);
+        }
+    }
+
+    pub fn index_of(&self, id: u64) -> Option<usize> {
+        self.timers.iter().position(|timer| timer.id == id)
+    }
+
+    pub fn index_of_uid(&self, uid: u64) -> Option<usize> {
+        self.timers
+            .iter()
+            .position(|timer| timer.serializeable_parts.uid == uid)
+    }
+
+    // Applies what another instance or tool changed in the save file, going
+    // by the file as it was last seen. Timers running or alarming here keep
+    // their own state over the file's
+    pub fn merge_external(
+        &mut self,
+        base: &[SerializeableTimerParts],
+        new: &[SerializeableTimerParts],
+    ) {
+        for parts in new {
+            let before = base.iter().find(|before| before.uid == parts.uid);
+
+            match (before, self.index_of_uid(parts.uid)) {
+                (None, None) => {
+                    self.add_timer_from_saved(parts.clone());
+                }
+                (Some(before), Some(i)) if before != parts => {
+                    if let TimerState::Stopped = self.timers[i].state {
+                        self.timers[i].set_parts(parts.clone());
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        for before in base {
+            if new.iter().any(|parts| parts.uid == before.uid) {
+                continue;
+            }
+
+            if let Some(i) = self.index_of_uid(before.uid) {
+                if let TimerState::Stopped = self.timers[i].state {
+                    self.delete_timer(i);
+                }
+            }
+        }
+    }
+
+    // Counts running timers down, sets off the ones that reached zero and
+    // tells the listeners where every timer is at
+    pub fn update_timers(&mut self) {
+        let mut alarmed = Vec::new();
+
+        for (i, timer) in self.timers.iter_mut().enumerate() {
+            let now = Instant::now();
+
+            match timer.state {
+                TimerState::Stopped => (),
+                TimerState::Running => {
+                    if let Some(tick) = timer.last_started {
+                        let time_elapsed = now.duration_since(tick);
+
+                        if let Some(new_time_left) =
+                            timer.time_left_at_last_tick.checked_sub(time_elapsed)
+                        {
+                            timer.serializeable_parts.time_left = new_time_left;
+                        } else {
+                            timer.serializeable_parts.time_left = Duration::ZERO;
+                            timer.time_left_at_last_tick = Duration::ZERO;
+                            timer.state = TimerState::Alarming;
+                            timer.alarm_started = Some(now);
+                            self.alarm_counter.increase_counter();
+                            alarmed.push(i);
+                        }
+                    } else {
+                        timer.last_started = Some(now);
+                    }
+                }
+                TimerState::Alarming => (),
+            }
+        }
+
+        for i in alarmed {
+            self.emit(TimerEventKind::Alarmed, i);
+        }
+
+        if !self.listeners.is_empty() {
+            let snapshots = self.snapshots();
+
+            for (_, listener) in self.listeners.iter_mut() {
+                listener.on_update(&snapshots);
+            }
+        }
+    }
+
+    // Drops every timer without emitting events, for replacing them with the
+    // saved ones
+    pub fn remove_all_timers(&mut self) {
+        for timer in &self.timers {
+            if let TimerState::Alarming = timer.state {
+                self.alarm_counter.decrease_counter();
+            }
+        }
+
+        self.timers.clear();
+    }
+
+    // Returns the index the timer was added at
+    pub fn push_timer(&mut self, mut timer: Timer) -> usize {
+        timer.id = self.next_timer_id;
+        self.next_timer_id += 1;
+
+        self.timers.push(timer);
+
+        self.timers.len() - 1
+    }
+
+    pub fn add_timer_from_saved(&mut self, parts: SerializeableTimerParts) -> usize {
+        self.push_timer(Timer::from_serializeable(parts))
+    }
+
+    pub fn delete_timer(&mut self, i: usize) {
+        self.emit(TimerEventKind::Deleted, i);
+
+        if let TimerState::Alarming = self.timers[i].state {
+            self.alarm_counter.decrease_counter()
+        }
+        self.timers.remove(i);
+    }
+
+    pub fn swap_timers(&mut self, i: usize, j: usize) {
+        self.timers.swap(i, j);
+    }
+
+    pub fn set_description(&mut self, i: usize, description: String) {
+        self.timers[i].serializeable_parts.description = description;
+    }
+
+    pub fn toggle_webhooks(&mut self, i: usize) {
+        self.timers[i].serializeable_parts.webhooks = !self.timers[i].serializeable_parts.webhooks;
+    }
+
+    // An alarming timer is acknowledged first, it can't keep ringing with a
+    // new length
+    pub fn change_length(&mut self, i: usize, length: Duration, change: LengthChange) {
+        if let TimerState::Alarming = self.timers[i].state {
+            self.acknowledge_timer(i);
+        }
+
+        self.timers[i].change_length(length, change);
+    }
+
+    pub fn start_timer(&mut self, i: usize) {
+        match self.timers[i].state {
+            TimerState::Stopped => {
+                self.timers[i].state = TimerState::Running;
+                self.timers[i].last_started = Some(Instant::now());
+                self.emit(TimerEventKind::Started, i);
+            }
+            TimerState::Running => (),
+            TimerState::Alarming => (),
+        }
+    }
+
+    pub fn pause_timer(&mut self, i: usize) {
+        match self.timers[i].state {
+            TimerState::Running => {
+                self.timers[i].state = TimerState::Stopped;
+                self.timers[i].last_started = Some(Instant::now());
+                self.timers[i].time_left_at_last_tick =
+                    self.timers[i].serializeable_parts.time_left;
+                self.emit(TimerEventKind::Paused, i);
+            }
+            TimerState::Alarming => self.acknowledge_timer(i),
+            TimerState::Stopped => (),
+        }
+    }
+
+    pub fn toggle_timer(&mut self, i: usize) {
+        match self.timers[i].state {
+            TimerState::Running => self.pause_timer(i),
+            TimerState::Stopped => self.start_timer(i),
+            TimerState::Alarming => (),
+        }
+    }
+
+    pub fn reset_timer(&mut self, i: usize) {
+        match self.timers[i].state {
+            TimerState::Running => (),
+            TimerState::Stopped => {
+                self.timers[i].serializeable_parts.time_left =
+                    self.timers[i].serializeable_parts.length;
+                self.timers[i].time_left_at_last_tick = self.timers[i].serializeable_parts.length;
+                self.timers[i].last_started = None;
+            }
+            TimerState::Alarming => self.acknowledge_timer(i),
+        }
+    }
+
+    // Silences an alarming timer and puts it back to its full length
+    pub fn acknowledge_timer(&mut self, i: usize) {
+        if let TimerState::Alarming = self.timers[i].state {
+            self.alarm_counter.decrease_counter();
+            self.timers[i].state = TimerState::Stopped;
+            self.timers[i].last_started = None;
+            self.timers[i].serializeable_parts.time_left =
+                self.timers[i].serializeable_parts.length;
+            self.timers[i].time_left_at_last_tick = self.timers[i].serializeable_parts.length;
+            self.emit(TimerEventKind::Acknowledged, i);
+            self.timers[i].alarm_started = None;
+        }
+    }
+
+    // Silences an alarming timer and runs it again for the snooze length
+    pub fn snooze_timer(&mut self, i: usize) {
+        if let TimerState::Alarming = self.timers[i].state {
+            self.alarm_counter.decrease_counter();
+            self.timers[i].state = TimerState::Running;
+            self.timers[i].last_started = Some(Instant::now());
+            self.timers[i].serializeable_parts.time_left = self.snooze_length;
+            self.timers[i].time_left_at_last_tick = self.snooze_length;
+            self.emit(TimerEventKind::Snoozed, i);
+            self.timers[i].alarm_started = None;
+        }
+    }
+
+    pub fn pause_all(&mut self) {
+        for i in 0..self.timers.len() {
+            if let TimerState::Running = self.timers[i].state {
+                self.pause_timer(i);
+            }
+        }
+    }
+
+    pub fn acknowledge_all(&mut self) {
+        for i in 0..self.timers.len() {
+            self.acknowledge_timer(i);
+        }
+    }
+
+    pub fn dump_json(&self) -> String {
+        saved_timers_json(self.timers.iter().map(|timer| &timer.serializeable_parts))
+    }
+
+    pub fn read_from_json(&mut self, json: &str) -> Result<(), LoadError> {
+        let timers = parse_saved_timers(json)?;
+
+        for timer in timers {
+            self.add_timer_from_saved(timer);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc, thread};
+
+    use super::*;
+
+    struct RecordingListener {
+        events: Rc<RefCell<Vec<(TimerEventKind, u64)>>>,
+    }
+
+    impl TimerEventListener for RecordingListener {
+        fn on_event(&mut self, event: &TimerEvent) {
+            self.events.borrow_mut().push((event.kind, event.id));
+        }
+    }
+
+    struct CountingAlarm {
+        ringing: Rc<RefCell<bool>>,
+    }
+
+    impl Alarm for CountingAlarm {
+        fn start(&mut self) {
+            *self.ringing.borrow_mut() = true;
+        }
+
+        fn stop(&mut self) {
+            *self.ringing.borrow_mut() = false;
+        }
+    }
+
+    struct Fixture {
+        engine: TimerEngine,
+        events: Rc<RefCell<Vec<(TimerEventKind, u64)>>>,
+        ringing: Rc<RefCell<bool>>,
+    }
+
+    impl Fixture {
+        fn new() -> Fixture {
+            let events = Rc::new(RefCell::new(Vec::new()));
+            let ringing = Rc::new(RefCell::new(false));
+
+            let mut engine = TimerEngine::new();
+            engine.add_listener(
+                "test",
+                Box::new(RecordingListener {
+                    events: Rc::clone(&events),
+                }),
+            );
+            engine.set_alarm(Box::new(CountingAlarm {
+                ringing: Rc::clone(&ringing),
+            }));
+
+            Fixture {
+                engine,
+                events,
+                ringing,
+            }
+        }
+
+        fn take_events(&self) -> Vec<TimerEventKind> {
+            self.events
+                .borrow_mut()
+                .drain(..)
+                .map(|(kind, _)| kind)
+                .collect()
+        }
+
+        fn ringing(&self) -> bool {
+            *self.ringing.borrow()
+        }
+
+        // A timer that goes off soon after it is started
+        fn push_short_timer(&mut self) -> usize {
+            self.engine
+                .push_timer(Timer::new(String::from("Short"), Duration::from_millis(20)))
+        }
+
+        // Starts the timer and ticks once it has run out
+        fn set_off(&mut self, i: usize) {
+            self.engine.start_timer(i);
+            thread::sleep(Duration::from_millis(40));
+            self.engine.update_timers();
+        }
+    }
+
+    fn parts(uid: u64, description: &str, time_left: u64) -> SerializeableTimerParts {
+        SerializeableTimerParts {
+            length: Duration::from_secs(60),
+            time_left: Duration::from_secs(time_left),
+            description: String::from(description),
+            webhooks: false,
+            uid,
+        }
+    }
+
+    #[test]
+    fn timers_get_ids_in_the_order_they_are_added() {
+        let mut engine = TimerEngine::new();
+
+        engine.push_timer(Timer::default());
+        engine.push_timer(Timer::default());
+        engine.delete_timer(0);
+        let i = engine.push_timer(Timer::default());
+
+        assert_eq!(i, 1);
+        assert_eq!(engine.timers()[0].id(), 1);
+        assert_eq!(engine.timers()[1].id(), 2);
+        assert_eq!(engine.index_of(2), Some(1));
+        assert_eq!(engine.index_of(0), None);
+    }
+
+    #[test]
+    fn starts_pauses_and_counts_down() {
+        let mut fixture = Fixture::new();
+        let i = fixture
+            .engine
+            .push_timer(Timer::new(String::from("Tea"), Duration::from_secs(60)));
+
+        fixture.engine.toggle_timer(i);
+        assert!(matches!(
+            fixture.engine.timers()[i].state(),
+            TimerState::Running
+        ));
+
+        thread::sleep(Duration::from_millis(20));
+        fixture.engine.update_timers();
+        fixture.engine.toggle_timer(i);
+
+        let timer = &fixture.engine.timers()[i];
+        assert!(matches!(timer.state(), TimerState::Stopped));
+        assert!(timer.get_time_left() < Duration::from_secs(60));
+        assert!(timer.get_time_left() > Duration::from_secs(59));
+
+        // A paused timer stays where it was
+        let time_left = timer.get_time_left();
+        thread::sleep(Duration::from_millis(20));
+        fixture.engine.update_timers();
+        assert_eq!(fixture.engine.timers()[i].get_time_left(), time_left);
+
+        assert_eq!(
+            fixture.take_events(),
+            [TimerEventKind::Started, TimerEventKind::Paused]
+        );
+    }
+
+    #[test]
+    fn alarms_when_it_runs_out() {
+        let mut fixture = Fixture::new();
+        let i = fixture.push_short_timer();
+
+        fixture.set_off(i);
+
+        let timer = &fixture.engine.timers()[i];
+        assert!(matches!(timer.state(), TimerState::Alarming));
+        assert_eq!(timer.get_time_left(), Duration::ZERO);
+        assert_eq!(fixture.engine.alarming_timers(), 1);
+        assert!(fixture.ringing());
+        assert_eq!(
+            fixture.take_events(),
+            [TimerEventKind::Started, TimerEventKind::Alarmed]
+        );
+
+        // Toggling doesn't silence it, only acknowledging or snoozing does
+        fixture.engine.toggle_timer(i);
+        assert!(matches!(
+            fixture.engine.timers()[i].state(),
+            TimerState::Alarming
+        ));
+    }
+
+    #[test]
+    fn acknowledging_puts_the_timer_back_to_its_length() {
+        let mut fixture = Fixture::new();
+        let i = fixture.push_short_timer();
+        fixture.set_off(i);
+        fixture.take_events();
+
+        fixture.engine.acknowledge_timer(i);
+
+        let timer = &fixture.engine.timers()[i];
+        assert!(matches!(timer.state(), TimerState::Stopped));
+        assert_eq!(timer.get_time_left(), Duration::from_millis(20));
+        assert_eq!(fixture.engine.alarming_timers(), 0);
+        assert!(!fixture.ringing());
+        assert_eq!(fixture.take_events(), [TimerEventKind::Acknowledged]);
+
+        // Only an alarming timer can be acknowledged
+        fixture.engine.acknowledge_timer(i);
+        assert!(fixture.take_events().is_empty());
+    }
+
+    #[test]
+    fn snoozing_runs_the_timer_for_the_snooze_length() {
+        let mut fixture = Fixture::new();
+        fixture.engine.snooze_length = Duration::from_secs(90);
+        let i = fixture.push_short_timer();
+        fixture.set_off(i);
+        fixture.take_events();
+
+        fixture.engine.snooze_timer(i);
+
+        let timer = &fixture.engine.timers()[i];
+        assert!(matches!(timer.state(), TimerState::Running));
+        assert_eq!(timer.get_time_left(), Duration::from_secs(90));
+        assert_eq!(timer.get_length(), Duration::from_millis(20));
+        assert!(!fixture.ringing());
+        assert_eq!(fixture.take_events(), [TimerEventKind::Snoozed]);
+    }
+
+    #[test]
+    fn only_resets_stopped_timers() {
+        let mut fixture = Fixture::new();
+        let i = fixture
+            .engine
+            .push_timer(Timer::new(String::from("Tea"), Duration::from_secs(60)));
+
+        fixture.engine.start_timer(i);
+        thread::sleep(Duration::from_millis(20));
+        fixture.engine.update_timers();
+
+        fixture.engine.reset_timer(i);
+        assert!(fixture.engine.timers()[i].get_time_left() < Duration::from_secs(60));
+
+        fixture.engine.pause_timer(i);
+        fixture.engine.reset_timer(i);
+        assert_eq!(
+            fixture.engine.timers()[i].get_time_left(),
+            Duration::from_secs(60)
+        );
+    }
+
+    #[test]
+    fn the_alarm_rings_until_the_last_alarming_timer_is_dealt_with() {
+        let mut fixture = Fixture::new();
+        let first = fixture.push_short_timer();
+        let second = fixture.push_short_timer();
+
+        fixture.engine.start_timer(first);
+        fixture.engine.start_timer(second);
+        thread::sleep(Duration::from_millis(40));
+        fixture.engine.update_timers();
+        assert_eq!(fixture.engine.alarming_timers(), 2);
+
+        fixture.engine.delete_timer(first);
+        assert!(fixture.ringing());
+
+        fixture.engine.acknowledge_all();
+        assert!(!fixture.ringing());
+        assert_eq!(fixture.engine.alarming_timers(), 0);
+        assert_eq!(
+            fixture.take_events(),
+            [
+                TimerEventKind::Started,
+                TimerEventKind::Started,
+                TimerEventKind::Alarmed,
+                TimerEventKind::Alarmed,
+                TimerEventKind::Deleted,
+                TimerEventKind::Acknowledged,
+            ]
+        );
+    }
+
+    #[test]
+    fn changing_the_length_of_an_alarming_timer_acknowledges_it() {
+        let mut fixture = Fixture::new();
+        let i = fixture.push_short_timer();
+        fixture.set_off(i);
+        fixture.take_events();
+
+        fixture
+            .engine
+            .change_length(i, Duration::from_secs(120), LengthChange::Restart);
+
+        let timer = &fixture.engine.timers()[i];
+        assert!(matches!(timer.state(), TimerState::Stopped));
+        assert_eq!(timer.get_time_left(), Duration::from_secs(120));
+        assert!(!fixture.ringing());
+        assert_eq!(fixture.take_events(), [TimerEventKind::Acknowledged]);
+    }
+
+    #[test]
+    fn removing_every_timer_stops_the_alarm_quietly() {
+        let mut fixture = Fixture::new();
+        let i = fixture.push_short_timer();
+        fixture.set_off(i);
+        fixture.take_events();
+
+        fixture.engine.remove_all_timers();
+
+        assert!(fixture.engine.timers().is_empty());
+        assert!(!fixture.ringing());
+        assert!(fixture.take_events().is_empty());
+    }
+
+    #[test]
+    fn merges_changes_made_elsewhere() {
+        let mut engine = TimerEngine::new();
+        let base = vec![
+            parts(1, "Kept", 60),
+            parts(2, "Edited", 60),
+            parts(3, "Removed", 60),
+            parts(4, "Running here", 60),
+            parts(5, "Removed but running here", 60),
+        ];
+        for timer in &base {
+            engine.add_timer_from_saved(timer.clone());
+        }
+        engine.start_timer(engine.index_of_uid(4).unwrap());
+        engine.start_timer(engine.index_of_uid(5).unwrap());
+
+        let new = vec![
+            parts(1, "Kept", 60),
+            parts(2, "Edited elsewhere", 30),
+            parts(4, "Edited while running here", 60),
+            parts(6, "Added", 60),
+        ];
+
+        engine.merge_external(&base, &new);
+
+        let descriptions: Vec<&str> = engine
+            .timers()
+            .iter()
+            .map(|timer| timer.description())
+            .collect();
+        assert_eq!(
+            descriptions,
+            [
+                "Kept",
+                "Edited elsewhere",
+                "Running here",
+                "Removed but running here",
+                "Added"
+            ]
+        );
+
+        let edited = &engine.timers()[1];
+        assert_eq!(edited.get_time_left(), Duration::from_secs(30));
+        assert!(matches!(edited.state(), TimerState::Stopped));
+    }
+
+    #[test]
+    fn round_trips_through_json() {
+        let mut engine = TimerEngine::new();
+        engine.add_timer_from_saved(parts(1, "Tea", 20));
+        engine.add_timer_from_saved(parts(2, "Pizza", 60));
+
+        let mut loaded = TimerEngine::new();
+        loaded.read_from_json(&engine.dump_json()).unwrap();
+
+        let saved: Vec<&SerializeableTimerParts> =
+            engine.timers().iter().map(|timer| timer.parts()).collect();
+        let read: Vec<&SerializeableTimerParts> =
+            loaded.timers().iter().map(|timer| timer.parts()).collect();
+        assert_eq!(saved, read);
+    }
+}
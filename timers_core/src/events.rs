@@ -0,0 +1,63 @@
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::timer::TimerSnapshot;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimerEventKind {
+    Started,
+    Paused,
+    Alarmed,
+    Acknowledged,
+    Snoozed,
+    Deleted,
+}
+
+impl TimerEventKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TimerEventKind::Started => "started",
+            TimerEventKind::Paused => "paused",
+            TimerEventKind::Alarmed => "alarmed",
+            TimerEventKind::Acknowledged => "acknowledged",
+            TimerEventKind::Snoozed => "snoozed",
+            TimerEventKind::Deleted => "deleted",
+        }
+    }
+}
+
+// A snapshot of the timer an event happened to, so listeners never need to
+// borrow the engine
+#[derive(Clone, Debug, Serialize)]
+pub struct TimerEvent {
+    #[serde(rename = "event")]
+    pub kind: TimerEventKind,
+    pub id: u64,
+    pub index: usize,
+    pub description: String,
+    #[serde(serialize_with = "serialize_seconds")]
+    pub length: Duration,
+    #[serde(serialize_with = "serialize_seconds")]
+    pub time_left: Duration,
+    #[serde(serialize_with = "serialize_seconds")]
+    pub overtime: Duration,
+    #[serde(skip)]
+    pub webhooks: bool,
+}
+
+fn serialize_seconds<S: serde::Serializer>(
+    duration: &Duration,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u64(duration.as_secs())
+}
+
+// Listeners are called from whatever drives the engine, so they must hand
+// any slow work off to another thread
+pub trait TimerEventListener {
+    fn on_event(&mut self, event: &TimerEvent);
+
+    fn on_update(&mut self, _timers: &[TimerSnapshot]) {}
+}
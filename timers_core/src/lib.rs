@@ -0,0 +1,45 @@
+//! The timer engine behind timers_tui, without any of the terminal UI, for
+//! embedding timers in other tools. A front end owns a TimerEngine, calls
+//! update_timers on its own tick, and hears about changes through
+//! TimerEventListener. Saving and loading is in persistence, and the sound
+//! for alarming timers is whatever Alarm the front end gives the engine
+//!
+//! ```
+//! use std::{thread, time::Duration};
+//!
+//! use timers_core::{Timer, TimerEngine, TimerEvent, TimerEventListener, TimerState};
+//!
+//! struct Printer;
+//!
+//! impl TimerEventListener for Printer {
+//!     fn on_event(&mut self, event: &TimerEvent) {
+//!         println!("{} {}", event.description, event.kind.name());
+//!     }
+//! }
+//!
+//! let mut engine = TimerEngine::new();
+//! engine.add_listener("printer", Box::new(Printer));
+//!
+//! let tea = engine.push_timer(Timer::new(String::from("Tea"), Duration::from_millis(10)));
+//! engine.start_timer(tea);
+//!
+//! thread::sleep(Duration::from_millis(20));
+//! engine.update_timers();
+//! assert!(matches!(engine.timers()[tea].state(), TimerState::Alarming));
+//!
+//! engine.acknowledge_timer(tea);
+//! assert!(matches!(engine.timers()[tea].state(), TimerState::Stopped));
+//! ```
+
+pub mod alarm;
+pub mod engine;
+pub mod events;
+pub mod persistence;
+pub mod timer;
+
+pub use alarm::{Alarm, SilentAlarm};
+pub use engine::TimerEngine;
+pub use events::{TimerEvent, TimerEventKind, TimerEventListener};
+pub use timer::{
+    change_key, new_uid, LengthChange, SerializeableTimerParts, Timer, TimerSnapshot, TimerState,
+};
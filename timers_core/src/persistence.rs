@@ -0,0 +1,627 @@
+use std::{
+    fmt::{self, Display},
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{
+    events::{TimerEvent, TimerEventListener},
+    timer::{change_key, SerializeableTimerParts, TimerSnapshot},
+};
+
+// Bump this when the saved timers change shape, and add a migration from the
+// previous version to MIGRATIONS
+pub const SAVE_FILE_VERSION: u64 = 3;
+
+// MIGRATIONS[n - 1] turns a version n file into a version n + 1 file
+const MIGRATIONS: [fn(Value) -> Value; 2] = [from_version_1, from_version_2];
+
+// Version 1 was the bare list of timers
+fn from_version_1(timers: Value) -> Value {
+    json!({ "version": 2, "timers": timers })
+}
+
+// 64-bit FNV-1a, written out so the hash never changes with the toolchain
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+// Version 3 gives every timer a uid. They are made from the timer itself, so
+// every instance migrating the same file agrees on them
+fn from_version_2(mut file: Value) -> Value {
+    if let Some(timers) = file.get_mut("timers").and_then(Value::as_array_mut) {
+        for (index, timer) in timers.iter_mut().enumerate() {
+            let mut bytes = (index as u64).to_le_bytes().to_vec();
+
+            for field in ["secs", "nanos"] {
+                let part = timer["length"][field].as_u64().unwrap_or_default();
+                bytes.extend(part.to_le_bytes());
+            }
+
+            bytes.extend(timer["description"].as_str().unwrap_or_default().as_bytes());
+
+            if let Some(timer) = timer.as_object_mut() {
+                timer.insert(String::from("uid"), json!(fnv1a(&bytes)));
+            }
+        }
+    }
+
+    file["version"] = json!(3);
+
+    file
+}
+
+#[derive(Deserialize)]
+struct SaveFile {
+    timers: Vec<SerializeableTimerParts>,
+}
+
+#[derive(Serialize)]
+struct SaveFileRef<'a> {
+    version: u64,
+    timers: Vec<&'a SerializeableTimerParts>,
+}
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    Parse(serde_json::Error),
+    NewerVersion(u64),
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Io(error) => write!(f, "{}", error),
+            LoadError::Parse(error) => write!(f, "{}", error),
+            LoadError::NewerVersion(version) => write!(
+                f,
+                "it was saved by a newer version of timers_tui (save file version {})",
+                version
+            ),
+        }
+    }
+}
+
+impl From<io::Error> for LoadError {
+    fn from(error: io::Error) -> LoadError {
+        LoadError::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for LoadError {
+    fn from(error: serde_json::Error) -> LoadError {
+        LoadError::Parse(error)
+    }
+}
+
+pub fn parse_saved_timers(json: &str) -> Result<Vec<SerializeableTimerParts>, LoadError> {
+    let mut value: Value = serde_json::from_str(json)?;
+
+    let mut version = match &value {
+        Value::Array(_) => 1,
+        _ => value
+            .get("version")
+            .and_then(Value::as_u64)
+            .ok_or_else(|| LoadError::Parse(serde::de::Error::missing_field("version")))?,
+    };
+
+    if version > SAVE_FILE_VERSION {
+        return Err(LoadError::NewerVersion(version));
+    }
+
+    while version < SAVE_FILE_VERSION {
+        value = MIGRATIONS[version as usize - 1](value);
+        version += 1;
+    }
+
+    Ok(serde_json::from_value::<SaveFile>(value)?.timers)
+}
+
+pub fn saved_timers_json<'a>(
+    timers: impl IntoIterator<Item = &'a SerializeableTimerParts>,
+) -> String {
+    serde_json::to_string_pretty(&SaveFileRef {
+        version: SAVE_FILE_VERSION,
+        timers: timers.into_iter().collect(),
+    })
+    .unwrap_or_default()
+}
+
+pub fn unix_now() -> Duration {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+// Moves a save file that could not be loaded out of the way, so saving never
+// overwrites it. It stays next to the original if it can, otherwise a copy
+// goes to fallback_dir
+pub fn keep_aside(save_file: &Path, fallback_dir: Option<PathBuf>) -> Option<PathBuf> {
+    let mut aside = save_file.as_os_str().to_owned();
+    aside.push(format!(".broken-{}", unix_now().as_secs()));
+    let aside = PathBuf::from(aside);
+
+    if fs::rename(save_file, &aside).is_ok() {
+        return Some(aside);
+    }
+
+    let aside = fallback_dir?.join(format!("broken_timers-{}.json", unix_now().as_secs()));
+
+    fs::copy(save_file, &aside).ok()?;
+
+    Some(aside)
+}
+
+pub const DEFAULT_BACKUPS: u32 = 3;
+pub const DEFAULT_AUTOSAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+// What this instance last wrote to each save file, so changes made by
+// others can be told apart from our own
+static LAST_WRITTEN: Mutex<Vec<(PathBuf, String)>> = Mutex::new(Vec::new());
+
+pub fn wrote_last(save_file: &Path, contents: &str) -> bool {
+    match LAST_WRITTEN.lock() {
+        Ok(written) => written
+            .iter()
+            .any(|(path, written)| path == save_file && written == contents),
+        Err(_) => false,
+    }
+}
+
+fn remember_written(save_file: &Path, contents: &str) {
+    if let Ok(mut written) = LAST_WRITTEN.lock() {
+        written.retain(|(path, _)| path != save_file);
+        written.push((save_file.to_path_buf(), contents.to_owned()));
+    }
+}
+
+// Backups are rotated at most this often, so a burst of saves doesn't push
+// every older copy out
+const BACKUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+// saved_timers.json.1 is the newest backup, .2 the one before it and so on
+pub fn backup_path(save_file: &Path, number: u32) -> PathBuf {
+    let mut path = save_file.as_os_str().to_owned();
+    path.push(format!(".{}", number));
+
+    PathBuf::from(path)
+}
+
+// Numbers the temp files of one process, so its own writers don't share one
+static TEMP_FILES: AtomicU64 = AtomicU64::new(0);
+
+// Writes a temp file next to the target, syncs it and renames it over the
+// target, so the file is always either the old or the new contents. Every
+// write has its own temp file, so other writers can't truncate or move it
+pub fn write_atomically(path: &Path, contents: &str) -> io::Result<()> {
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(format!(
+        ".{}.{}.tmp",
+        process::id(),
+        TEMP_FILES.fetch_add(1, Ordering::Relaxed)
+    ));
+    let temp_path = PathBuf::from(temp_path);
+
+    let written = File::create(&temp_path).and_then(|mut file| {
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+
+        fs::rename(&temp_path, path)
+    });
+
+    if let Err(error) = written {
+        let _ = fs::remove_file(&temp_path);
+        return Err(error);
+    }
+
+    // Make the rename itself durable
+    if let Some(parent) = path.parent() {
+        if let Ok(directory) = File::open(parent) {
+            let _ = directory.sync_all();
+        }
+    }
+
+    Ok(())
+}
+
+fn backup_is_due(save_file: &Path) -> bool {
+    let newest_backup = fs::metadata(backup_path(save_file, 1)).and_then(|meta| meta.modified());
+
+    match newest_backup {
+        Ok(modified) => SystemTime::now()
+            .duration_since(modified)
+            .map_or(true, |age| age >= BACKUP_INTERVAL),
+        Err(_) => true,
+    }
+}
+
+fn rotate_backups(save_file: &Path, backups: u32) -> io::Result<()> {
+    for number in (1..backups).rev() {
+        let from = backup_path(save_file, number);
+
+        if from.exists() {
+            fs::rename(&from, backup_path(save_file, number + 1))?;
+        }
+    }
+
+    fs::copy(save_file, backup_path(save_file, 1))?;
+
+    Ok(())
+}
+
+// Saves the timers json, first moving the current file into the backups if
+// the newest backup is old enough
+pub fn save_timers_file(save_file: &Path, json: &str, backups: u32) -> io::Result<()> {
+    if let Some(parent) = save_file.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    if backups > 0 && save_file.exists() && backup_is_due(save_file) {
+        rotate_backups(save_file, backups)?;
+    }
+
+    // Before the rename, so the watcher never sees it as someone else's
+    remember_written(save_file, json);
+
+    write_atomically(save_file, json)
+}
+
+// Saves the timers whenever something other than a countdown changes, and
+// every interval while timers are running
+pub struct Autosave {
+    save_file: PathBuf,
+    backups: u32,
+    interval: Option<Duration>,
+    last_change_key: Option<String>,
+    last_contents: String,
+    last_saved: Instant,
+    // Only the first of a run of failed saves is logged, it is retried often
+    failing: bool,
+}
+
+impl Autosave {
+    pub fn new(save_file: PathBuf, backups: u32, interval: Option<Duration>) -> Autosave {
+        Autosave {
+            save_file,
+            backups,
+            interval,
+            last_change_key: None,
+            last_contents: String::new(),
+            last_saved: Instant::now(),
+            failing: false,
+        }
+    }
+}
+
+impl TimerEventListener for Autosave {
+    fn on_event(&mut self, _event: &TimerEvent) {}
+
+    fn on_update(&mut self, timers: &[TimerSnapshot]) {
+        let contents = saved_timers_json(timers.iter().map(|timer| &timer.parts));
+        let key = change_key(timers);
+
+        let changed = match &self.last_change_key {
+            Some(last_change_key) => *last_change_key != key,
+            // What was just loaded is already on disk
+            None => {
+                self.last_change_key = Some(key);
+                self.last_contents = contents;
+                return;
+            }
+        };
+
+        let interval_passed = self
+            .interval
+            .is_some_and(|interval| self.last_saved.elapsed() >= interval);
+
+        let countdown_due = interval_passed && contents != self.last_contents;
+
+        if !changed && !countdown_due {
+            return;
+        }
+
+        match save_timers_file(&self.save_file, &contents, self.backups) {
+            Ok(()) => {
+                if self.failing {
+                    info!("Saving to {} works again", self.save_file.display());
+                }
+
+                self.last_change_key = Some(key);
+                self.last_contents = contents;
+                self.last_saved = Instant::now();
+                self.failing = false;
+            }
+            Err(error) if !self.failing => {
+                error!("Could not save to {}: {}", self.save_file.display(), error);
+                self.failing = true;
+            }
+            Err(_) => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{process, thread};
+
+    use crate::timer::TimerState;
+
+    use super::*;
+
+    const VERSION_1: &str = r#"[
+        {
+            "length": { "secs": 180, "nanos": 0 },
+            "time_left": { "secs": 95, "nanos": 0 },
+            "description": "Tea"
+        }
+    ]"#;
+
+    const VERSION_2: &str = r#"{
+        "version": 2,
+        "timers": [
+            {
+                "length": { "secs": 180, "nanos": 0 },
+                "time_left": { "secs": 95, "nanos": 0 },
+                "description": "Tea",
+                "webhooks": true
+            },
+            {
+                "length": { "secs": 720, "nanos": 0 },
+                "time_left": { "secs": 720, "nanos": 0 },
+                "description": "Pizza"
+            }
+        ]
+    }"#;
+
+    // Whatever version of Rust builds it, the first timer of VERSION_2 always
+    // migrates to this uid
+    const MIGRATED_TEA_UID: u64 = 1386572626079607005;
+
+    const VERSION_3: &str = r#"{
+        "version": 3,
+        "timers": [
+            {
+                "length": { "secs": 180, "nanos": 0 },
+                "time_left": { "secs": 95, "nanos": 0 },
+                "description": "Tea",
+                "webhooks": true,
+                "uid": 42
+            }
+        ]
+    }"#;
+
+    // A directory of its own for each test, emptied first
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("timers_core-{}-{}", process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        dir
+    }
+
+    fn snapshot(state: TimerState, time_left: u64) -> TimerSnapshot {
+        TimerSnapshot {
+            id: 0,
+            state,
+            parts: SerializeableTimerParts {
+                length: Duration::from_secs(180),
+                time_left: Duration::from_secs(time_left),
+                description: String::from("Tea"),
+                webhooks: false,
+                uid: 42,
+            },
+        }
+    }
+
+    #[test]
+    fn reads_version_1_files() {
+        let timers = parse_saved_timers(VERSION_1).unwrap();
+
+        assert_eq!(timers.len(), 1);
+        assert_eq!(timers[0].description, "Tea");
+        assert_eq!(timers[0].length, Duration::from_secs(180));
+        assert_eq!(timers[0].time_left, Duration::from_secs(95));
+        assert!(!timers[0].webhooks);
+    }
+
+    #[test]
+    fn reads_version_2_files_with_the_same_uids_every_time() {
+        let timers = parse_saved_timers(VERSION_2).unwrap();
+
+        assert_eq!(timers.len(), 2);
+        assert!(timers[0].webhooks);
+        assert_eq!(timers[1].description, "Pizza");
+        assert_ne!(timers[0].uid, timers[1].uid);
+
+        let again = parse_saved_timers(VERSION_2).unwrap();
+        assert_eq!(timers, again);
+    }
+
+    #[test]
+    fn migrated_uids_never_change() {
+        assert_eq!(fnv1a(b""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a(b"a"), 0xaf63dc4c8601ec8c);
+
+        let timers = parse_saved_timers(VERSION_2).unwrap();
+        assert_eq!(timers[0].uid, MIGRATED_TEA_UID);
+    }
+
+    #[test]
+    fn reads_version_3_files() {
+        let timers = parse_saved_timers(VERSION_3).unwrap();
+
+        assert_eq!(timers.len(), 1);
+        assert_eq!(timers[0].uid, 42);
+        assert!(timers[0].webhooks);
+    }
+
+    #[test]
+    fn refuses_files_from_newer_versions() {
+        let newer = format!(
+            r#"{{ "version": {}, "timers": [] }}"#,
+            SAVE_FILE_VERSION + 1
+        );
+
+        assert!(matches!(
+            parse_saved_timers(&newer),
+            Err(LoadError::NewerVersion(version)) if version == SAVE_FILE_VERSION + 1
+        ));
+        assert!(matches!(
+            parse_saved_timers(r#"{ "timers": [] }"#),
+            Err(LoadError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn writes_the_current_version() {
+        let timers = parse_saved_timers(VERSION_2).unwrap();
+        let json = saved_timers_json(&timers);
+
+        let value: Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["version"], json!(SAVE_FILE_VERSION));
+        assert_eq!(parse_saved_timers(&json).unwrap(), timers);
+    }
+
+    #[test]
+    fn autosave_skips_what_was_just_loaded() {
+        let save_file = test_dir("autosave-loaded").join("saved_timers.json");
+        let mut autosave = Autosave::new(save_file.clone(), 0, None);
+
+        autosave.on_update(&[snapshot(TimerState::Stopped, 180)]);
+
+        assert!(!save_file.exists());
+    }
+
+    #[test]
+    fn autosave_saves_changes() {
+        let save_file = test_dir("autosave-changes").join("saved_timers.json");
+        let mut autosave = Autosave::new(save_file.clone(), 0, None);
+
+        autosave.on_update(&[snapshot(TimerState::Stopped, 180)]);
+        autosave.on_update(&[snapshot(TimerState::Running, 180)]);
+
+        let saved = parse_saved_timers(&fs::read_to_string(&save_file).unwrap()).unwrap();
+        assert_eq!(saved.len(), 1);
+        assert!(wrote_last(
+            &save_file,
+            &fs::read_to_string(&save_file).unwrap()
+        ));
+    }
+
+    #[test]
+    fn autosave_saves_turning_webhooks_on() {
+        let save_file = test_dir("autosave-webhooks").join("saved_timers.json");
+        let mut autosave = Autosave::new(save_file.clone(), 0, None);
+
+        let mut timer = snapshot(TimerState::Stopped, 180);
+        autosave.on_update(&[timer.clone()]);
+
+        timer.parts.webhooks = true;
+        autosave.on_update(&[timer]);
+
+        let saved = parse_saved_timers(&fs::read_to_string(&save_file).unwrap()).unwrap();
+        assert!(saved[0].webhooks);
+    }
+
+    #[test]
+    fn autosave_leaves_countdowns_to_the_interval() {
+        let save_file = test_dir("autosave-countdown").join("saved_timers.json");
+        let mut autosave = Autosave::new(save_file.clone(), 0, None);
+
+        autosave.on_update(&[snapshot(TimerState::Running, 180)]);
+        autosave.on_update(&[snapshot(TimerState::Running, 170)]);
+        assert!(!save_file.exists());
+
+        let mut autosave = Autosave::new(save_file.clone(), 0, Some(Duration::from_millis(20)));
+
+        autosave.on_update(&[snapshot(TimerState::Running, 180)]);
+        autosave.on_update(&[snapshot(TimerState::Running, 179)]);
+        assert!(!save_file.exists());
+
+        thread::sleep(Duration::from_millis(30));
+        autosave.on_update(&[snapshot(TimerState::Running, 178)]);
+
+        let saved = parse_saved_timers(&fs::read_to_string(&save_file).unwrap()).unwrap();
+        assert_eq!(saved[0].time_left, Duration::from_secs(178));
+    }
+
+    #[test]
+    fn keeps_backups_of_earlier_saves() {
+        let save_file = test_dir("backups").join("saved_timers.json");
+
+        save_timers_file(&save_file, "first", 2).unwrap();
+        save_timers_file(&save_file, "second", 2).unwrap();
+
+        assert_eq!(fs::read_to_string(&save_file).unwrap(), "second");
+        assert_eq!(
+            fs::read_to_string(backup_path(&save_file, 1)).unwrap(),
+            "first"
+        );
+        assert!(!backup_path(&save_file, 2).exists());
+    }
+
+    #[test]
+    fn writers_at_the_same_time_each_use_their_own_temp_file() {
+        let dir = test_dir("atomic");
+        let save_file = dir.join("saved_timers.json");
+
+        let writers: Vec<_> = (0..8)
+            .map(|writer| {
+                let save_file = save_file.clone();
+
+                thread::spawn(move || {
+                    for _ in 0..20 {
+                        write_atomically(&save_file, &format!("writer {}", writer)).unwrap();
+                    }
+                })
+            })
+            .collect();
+
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        assert!(fs::read_to_string(&save_file)
+            .unwrap()
+            .starts_with("writer "));
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn a_failed_write_leaves_no_temp_file() {
+        let dir = test_dir("atomic-failed");
+
+        // A directory can't be renamed over
+        let save_file = dir.join("saved_timers.json");
+        fs::create_dir(&save_file).unwrap();
+        fs::write(save_file.join("inside"), "").unwrap();
+
+        assert!(write_atomically(&save_file, "timers").is_err());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn moves_broken_files_aside() {
+        let save_file = test_dir("broken").join("saved_timers.json");
+        fs::write(&save_file, "not json").unwrap();
+
+        let aside = keep_aside(&save_file, None).unwrap();
+
+        assert!(!save_file.exists());
+        assert_eq!(fs::read_to_string(aside).unwrap(), "not json");
+    }
+}
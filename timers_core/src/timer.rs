@@ -0,0 +1,312 @@
+use std::{
+    process,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::persistence::unix_now;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub enum TimerState {
+    Stopped,
+    Running,
+    Alarming,
+}
+
+impl TimerState {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TimerState::Stopped => "stopped",
+            TimerState::Running => "running",
+            TimerState::Alarming => "alarming",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SerializeableTimerParts {
+    pub length: Duration,
+    pub time_left: Duration,
+    pub description: String,
+    #[serde(default)]
+    pub webhooks: bool,
+    // Identifies the timer across save files and instances, unlike the
+    // runtime id. Timers added over the HTTP API get a new one
+    #[serde(default = "new_uid")]
+    pub uid: u64,
+}
+
+// The last timestamp handed out, so uids made in the same nanosecond differ
+static LAST_UID_TIME: AtomicU64 = AtomicU64::new(0);
+
+// A new uid from the time in nanoseconds, made strictly increasing within
+// the process, with the process id folded in so instances started together
+// don't collide. The mix is reversible, so different inputs never give the
+// same uid
+pub fn new_uid() -> u64 {
+    let now = unix_now().as_nanos() as u64;
+
+    let previous = LAST_UID_TIME
+        .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |last| {
+            Some(now.max(last + 1))
+        })
+        .unwrap_or_default();
+    let time = now.max(previous + 1);
+
+    mix(time ^ u64::from(process::id()).rotate_right(16))
+}
+
+// The splitmix64 finalizer
+fn mix(mut value: u64) -> u64 {
+    value = (value ^ (value >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94d049bb133111eb);
+
+    value ^ (value >> 31)
+}
+
+impl SerializeableTimerParts {
+    fn new(description: String, length: Duration, time_left: Duration) -> SerializeableTimerParts {
+        SerializeableTimerParts {
+            length,
+            time_left,
+            description,
+            webhooks: false,
+            uid: new_uid(),
+        }
+    }
+}
+
+// Only the engine changes a timer, so the counting down and the alarm always
+// agree with its state
+pub struct Timer {
+    pub(crate) id: u64,
+    pub(crate) serializeable_parts: SerializeableTimerParts,
+    pub(crate) last_started: Option<Instant>,
+    pub(crate) time_left_at_last_tick: Duration,
+    pub(crate) alarm_started: Option<Instant>,
+    pub(crate) state: TimerState,
+}
+
+impl Default for Timer {
+    fn default() -> Timer {
+        Timer::new(String::from("New Timer"), Duration::from_secs(300))
+    }
+}
+
+impl Timer {
+    pub fn new(description: String, length: Duration) -> Timer {
+        Timer {
+            id: 0,
+            serializeable_parts: SerializeableTimerParts::new(description, length, length),
+            last_started: None,
+            time_left_at_last_tick: length,
+            alarm_started: None,
+            state: TimerState::Stopped,
+        }
+    }
+
+    pub fn from_serializeable(parts: SerializeableTimerParts) -> Timer {
+        let time_left_at_last_tick = parts.time_left;
+
+        Timer {
+            id: 0,
+            serializeable_parts: parts,
+            last_started: None,
+            time_left_at_last_tick,
+            alarm_started: None,
+            state: TimerState::Stopped,
+        }
+    }
+
+    // Takes saved parts from elsewhere, keeping the state it is in
+    pub(crate) fn set_parts(&mut self, parts: SerializeableTimerParts) {
+        self.time_left_at_last_tick = parts.time_left;
+        self.serializeable_parts = parts;
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn state(&self) -> TimerState {
+        self.state
+    }
+
+    pub fn parts(&self) -> &SerializeableTimerParts {
+        &self.serializeable_parts
+    }
+
+    pub fn description(&self) -> &str {
+        &self.serializeable_parts.description
+    }
+
+    pub fn clone_description(&self) -> String {
+        self.serializeable_parts.description.clone()
+    }
+
+    pub fn get_length(&self) -> Duration {
+        self.serializeable_parts.length
+    }
+
+    pub fn get_time_left(&self) -> Duration {
+        self.serializeable_parts.time_left
+    }
+
+    pub fn has_webhooks(&self) -> bool {
+        self.serializeable_parts.webhooks
+    }
+
+    // How long the timer has been alarming for
+    pub fn get_overtime(&self) -> Duration {
+        match self.alarm_started {
+            Some(alarm_started) => alarm_started.elapsed(),
+            None => Duration::ZERO,
+        }
+    }
+
+    pub fn snapshot(&self) -> TimerSnapshot {
+        TimerSnapshot {
+            id: self.id,
+            state: self.state,
+            parts: self.serializeable_parts.clone(),
+        }
+    }
+
+    // Alarming timers first, then running ones, for sorting by what needs
+    // attention
+    pub fn state_rank(&self) -> u8 {
+        match self.state {
+            TimerState::Alarming => 0,
+            TimerState::Running => 1,
+            TimerState::Stopped => 2,
+        }
+    }
+
+    pub(crate) fn change_length(&mut self, length: Duration, change: LengthChange) {
+        let elapsed = self.get_length().saturating_sub(self.get_time_left());
+
+        let time_left = match change {
+            LengthChange::KeepElapsed => length.saturating_sub(elapsed),
+            LengthChange::KeepRemaining => self.get_time_left().min(length),
+            LengthChange::Restart => length,
+        };
+
+        self.serializeable_parts.length = length;
+        self.serializeable_parts.time_left = time_left;
+        self.time_left_at_last_tick = time_left;
+
+        // Restart the tick from here so a running timer keeps counting down
+        // from the new time left instead of the old one
+        if let TimerState::Running = self.state {
+            self.last_started = Some(Instant::now());
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum LengthChange {
+    KeepElapsed,
+    KeepRemaining,
+    Restart,
+}
+
+// What integrations get to see of every timer after each update
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TimerSnapshot {
+    pub id: u64,
+    pub state: TimerState,
+    #[serde(flatten)]
+    pub parts: SerializeableTimerParts,
+}
+
+// Changes whenever anything about the timers does, apart from running timers
+// counting down, which happens on every tick. Listeners that write files or
+// push updates compare it with the key they last acted on
+pub fn change_key(timers: &[TimerSnapshot]) -> String {
+    let key: Vec<_> = timers
+        .iter()
+        .map(|timer| {
+            let time_left = match timer.state {
+                TimerState::Running => None,
+                _ => Some(timer.parts.time_left),
+            };
+
+            (
+                timer.id,
+                timer.parts.uid,
+                timer.state,
+                &timer.parts.description,
+                timer.parts.length,
+                time_left,
+                timer.parts.webhooks,
+            )
+        })
+        .collect();
+
+    format!("{:?}", key)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, thread};
+
+    use super::*;
+
+    #[test]
+    fn uids_are_unique() {
+        let mut uids: HashSet<u64> = (0..10_000).map(|_| new_uid()).collect();
+
+        let handles: Vec<_> = (0..4)
+            .map(|_| thread::spawn(|| (0..1_000).map(|_| new_uid()).collect::<Vec<_>>()))
+            .collect();
+
+        for handle in handles {
+            for uid in handle.join().unwrap() {
+                assert!(uids.insert(uid));
+            }
+        }
+
+        assert_eq!(uids.len(), 14_000);
+    }
+
+    #[test]
+    fn change_key_ignores_running_countdowns_only() {
+        let mut timer = Timer::new(String::from("Tea"), Duration::from_secs(300)).snapshot();
+        timer.state = TimerState::Running;
+        let key = change_key(&[timer.clone()]);
+
+        timer.parts.time_left = Duration::from_secs(290);
+        assert_eq!(change_key(&[timer.clone()]), key);
+
+        timer.state = TimerState::Stopped;
+        let stopped = change_key(&[timer.clone()]);
+        assert_ne!(stopped, key);
+
+        timer.parts.time_left = Duration::from_secs(280);
+        assert_ne!(change_key(&[timer.clone()]), stopped);
+
+        timer.parts.time_left = Duration::from_secs(290);
+        timer.parts.webhooks = true;
+        assert_ne!(change_key(&[timer]), stopped);
+    }
+
+    #[test]
+    fn changes_length_keeping_what_was_asked() {
+        let mut timer = Timer::new(String::from("Tea"), Duration::from_secs(300));
+        timer.serializeable_parts.time_left = Duration::from_secs(200);
+
+        let mut keep_elapsed = Timer::from_serializeable(timer.parts().clone());
+        keep_elapsed.change_length(Duration::from_secs(600), LengthChange::KeepElapsed);
+        assert_eq!(keep_elapsed.get_time_left(), Duration::from_secs(500));
+
+        let mut keep_remaining = Timer::from_serializeable(timer.parts().clone());
+        keep_remaining.change_length(Duration::from_secs(60), LengthChange::KeepRemaining);
+        assert_eq!(keep_remaining.get_time_left(), Duration::from_secs(60));
+
+        timer.change_length(Duration::from_secs(600), LengthChange::Restart);
+        assert_eq!(timer.get_time_left(), Duration::from_secs(600));
+        assert_eq!(timer.get_length(), Duration::from_secs(600));
+    }
+}